@@ -24,6 +24,7 @@ pub fn main() {
         HotReloadPlugin {
             auto_watch: true,
             bevy_dylib: true,
+            reload_key: Some(KeyCode::F5),
             ..default()
         },
     ))
@@ -40,7 +41,9 @@ const SHAPES_X_EXTENT: f32 = 14.0;
 const EXTRUSION_X_EXTENT: f32 = 16.0;
 const Z_EXTENT: f32 = 5.0;
 
-fn setup(
+// Despawns what it spawned and runs again with the new code after each reload
+#[make_hot(rerun_on_reload)]
+pub fn setup(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut images: ResMut<Assets<Image>>,
@@ -8,150 +8,825 @@ use proc_macro_crate::{crate_name, FoundCrate};
 #[cfg(feature = "hot_reload")]
 use quote::{quote, ToTokens};
 #[cfg(feature = "hot_reload")]
-use std::{
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
-};
+use syn::{parse::Parser, parse_quote, punctuated::Punctuated, FnArg, ItemFn, Meta, Token};
+
+#[cfg(all(test, feature = "hot_reload"))]
+mod tests;
+
+/// Options passed as `#[make_hot(...)]`
 #[cfg(feature = "hot_reload")]
-use syn::{parse_macro_input, FnArg, ItemFn};
+#[derive(Default)]
+struct HotArgs {
+    /// Not a system, only takes FFI-safe data and is called through `extern "C"`.
+    /// Finds the library without a system param.
+    bridge: bool,
+    /// Reset `Local<T>` params to `T::default()` after each reload
+    reset_locals: bool,
+    /// Despawn what the system spawned and run it again after each reload
+    rerun_on_reload: bool,
+    /// Types a generic function is exported for, e.g. `instances(Goblin, Orc)`
+    instances: Vec<syn::Path>,
+    /// Exported as `ridiculous_bevy_hot_<name>_<hash>` instead of using the module path
+    name: Option<syn::LitStr>,
+}
+
+#[cfg(feature = "hot_reload")]
+impl HotArgs {
+    fn parse(args: proc_macro2::TokenStream) -> syn::Result<Self> {
+        let args = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(args)?;
+        let mut hot_args = HotArgs::default();
+        // Options that only apply to systems
+        let mut system_options = Vec::new();
+        for arg in args {
+            match arg {
+                Meta::Path(path) if path.is_ident("bridge") => {
+                    hot_args.bridge = true;
+                }
+                Meta::Path(path) if path.is_ident("reset_locals") => {
+                    hot_args.reset_locals = true;
+                    system_options.push(path);
+                }
+                Meta::Path(path) if path.is_ident("rerun_on_reload") => {
+                    hot_args.rerun_on_reload = true;
+                    system_options.push(path);
+                }
+                Meta::NameValue(name_value) if name_value.path.is_ident("name") => {
+                    let syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(name),
+                        ..
+                    }) = name_value.value
+                    else {
+                        return Err(syn::Error::new_spanned(
+                            name_value.value,
+                            "expected a string, e.g. `name = \"player::rotate\"`",
+                        ));
+                    };
+                    hot_args.name = Some(name);
+                }
+                Meta::List(list) if list.path.is_ident("instances") => {
+                    let instances =
+                        list.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)?;
+                    if instances.is_empty() {
+                        return Err(syn::Error::new_spanned(
+                            list,
+                            "`instances` needs at least one type, e.g. `instances(Goblin, Orc)`",
+                        ));
+                    }
+                    for path in instances {
+                        if let Some(segment) = path
+                            .segments
+                            .iter()
+                            .find(|segment| !segment.arguments.is_none())
+                        {
+                            return Err(syn::Error::new_spanned(
+                                segment,
+                                "expected a type name without generic arguments",
+                            ));
+                        }
+                        hot_args.instances.push(path);
+                    }
+                }
+                arg => {
+                    return Err(syn::Error::new_spanned(
+                        arg,
+                        "unknown make_hot option, expected `bridge`, `reset_locals`, `rerun_on_reload`, `instances(..)` or `name = \"..\"`",
+                    ))
+                }
+            }
+        }
+        if let (true, false) = (hot_args.rerun_on_reload, hot_args.instances.is_empty()) {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "`rerun_on_reload` can't be used with `instances`, startup systems can't be generic",
+            ));
+        }
+        if let (true, Some(path)) = (hot_args.bridge, system_options.first()) {
+            return Err(syn::Error::new_spanned(
+                path,
+                format!(
+                    "`{}` can't be used with `bridge`, bridge functions are not systems",
+                    path.to_token_stream()
+                ),
+            ));
+        }
+        Ok(hot_args)
+    }
+}
 
 #[proc_macro_attribute]
-pub fn make_hot(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn make_hot(attr: TokenStream, item: TokenStream) -> TokenStream {
     #[cfg(not(feature = "hot_reload"))]
     {
-        return item;
+        let _ = attr;
+        item
     }
 
     #[cfg(feature = "hot_reload")]
     {
-        let ast = parse_macro_input!(item as ItemFn);
-
-        let fn_name = &ast.sig.ident;
-
-        // Try to make unique hash that is appended onto function name
-        // So that there can be multiple functions with the same name
-        // Waiting on https://github.com/rust-lang/rust/issues/54725
-        // For more things to hash like source_file(), etc...
-        let mut hasher = DefaultHasher::new();
-        // An advantage of including the sig here is that it will crash
-        // is the user changes the sig.
-        ast.sig.to_token_stream().to_string().hash(&mut hasher);
-        //format!("{:?}", Span::call_site().unwrap().source_file()).hash(&mut hasher);
-        let hash = hasher.finish();
-
-        let mut args = Vec::new();
-        let mut args_hot_func = Vec::new();
-        let mut arg_names = Vec::new();
-        let mut arg_types = Vec::new();
-
-        let mut hot_arg_names = Vec::new();
-        let mut hot_arg_types = Vec::new();
-
-        for arg in &ast.sig.inputs {
-            args.push(arg.clone());
-            args_hot_func.push(arg.clone());
-            match arg {
-                FnArg::Receiver(_) => (),
-                FnArg::Typed(pt) => {
-                    let mut pt = pt.clone();
-                    match *pt.pat {
-                        syn::Pat::Ident(ref mut id) => {
-                            arg_names.push(id.ident.clone());
-                            let name = id.ident.clone();
-                            hot_arg_names.push(quote! { #name });
-                        }
-                        _ => (),
-                    }
-                    arg_types.push(pt.ty.clone())
+        make_hot_impl(attr.into(), item.into(), crate_found())
+            .unwrap_or_else(syn::Error::into_compile_error)
+            .into()
+    }
+}
+
+/// Applies `#[make_hot]` to every `pub fn` in an inline module whose arguments are all
+/// Bevy system params. Functions marked `#[not_hot]` or `#[make_hot(..)]` are left alone.
+#[proc_macro_attribute]
+pub fn hot_module(attr: TokenStream, item: TokenStream) -> TokenStream {
+    #[cfg(not(feature = "hot_reload"))]
+    let crate_found = proc_macro2::TokenStream::new();
+    #[cfg(feature = "hot_reload")]
+    let crate_found = crate_found();
+
+    hot_module_impl(attr.into(), item.into(), crate_found)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Path to ridiculous_bevy_hot_reloading from the crate using the macro
+#[cfg(feature = "hot_reload")]
+fn crate_found() -> proc_macro2::TokenStream {
+    let found_crate = crate_name("ridiculous_bevy_hot_reloading")
+        .expect("ridiculous_bevy_hot_reloading is present in `Cargo.toml`");
+
+    match found_crate {
+        FoundCrate::Itself => quote!(crate),
+        FoundCrate::Name(name) => {
+            let ident = Ident::new(&name, Span::call_site());
+            quote!( #ident)
+        }
+    }
+}
+
+/// Expands `#[hot_module]`, without the `hot_reload` feature it only removes `#[not_hot]`
+#[cfg_attr(not(feature = "hot_reload"), allow(unused_variables))]
+fn hot_module_impl(
+    attr: proc_macro2::TokenStream,
+    item: proc_macro2::TokenStream,
+    crate_found: proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    if let Some(token) = attr.into_iter().next() {
+        return Err(syn::Error::new_spanned(
+            token,
+            "`hot_module` takes no options",
+        ));
+    }
+    let mut module: syn::ItemMod = syn::parse2(item)?;
+    let Some((_, items)) = &mut module.content else {
+        return Err(syn::Error::new_spanned(
+            &module,
+            "`hot_module` needs an inline module, `mod name { .. }`",
+        ));
+    };
+    for item in items.iter_mut() {
+        let syn::Item::Fn(item_fn) = item else {
+            continue;
+        };
+        let attrs_before = item_fn.attrs.len();
+        item_fn
+            .attrs
+            .retain(|attr| !attr.path().is_ident("not_hot"));
+        if item_fn.attrs.len() != attrs_before {
+            continue;
+        }
+        #[cfg(feature = "hot_reload")]
+        if is_system(item_fn) {
+            let hot = make_hot_impl(
+                proc_macro2::TokenStream::new(),
+                item_fn.to_token_stream(),
+                crate_found.clone(),
+            )?;
+            *item = syn::Item::Verbatim(hot);
+        }
+    }
+    Ok(quote::ToTokens::into_token_stream(module))
+}
+
+/// A `pub fn` that isn't already hot and only takes params bevy knows, e.g.
+/// `Query`, `Res` or `Commands`. Custom `SystemParam`s need an explicit `#[make_hot]`.
+#[cfg(feature = "hot_reload")]
+fn is_system(item_fn: &ItemFn) -> bool {
+    const SYSTEM_PARAMS: &[&str] = &[
+        "Commands",
+        "EventReader",
+        "EventWriter",
+        "Gizmos",
+        "Local",
+        "NonSend",
+        "NonSendMut",
+        "ParamSet",
+        "Populated",
+        "Query",
+        "Res",
+        "ResMut",
+        "Single",
+    ];
+    fn is_system_param(ty: &syn::Type) -> bool {
+        let syn::Type::Path(type_path) = ty else {
+            return false;
+        };
+        let Some(last) = type_path.path.segments.last() else {
+            return false;
+        };
+        if last.ident == "Option" {
+            let syn::PathArguments::AngleBracketed(args) = &last.arguments else {
+                return false;
+            };
+            return matches!(args.args.first(), Some(syn::GenericArgument::Type(ty)) if is_system_param(ty));
+        }
+        SYSTEM_PARAMS.iter().any(|param| last.ident == param)
+    }
+
+    let sig = &item_fn.sig;
+    matches!(item_fn.vis, syn::Visibility::Public(_))
+        && !item_fn.attrs.iter().any(|attr| {
+            attr.path()
+                .segments
+                .last()
+                .is_some_and(|s| s.ident == "make_hot")
+        })
+        && sig.asyncness.is_none()
+        && sig.constness.is_none()
+        && sig.unsafety.is_none()
+        && sig.abi.is_none()
+        && sig.generics.params.is_empty()
+        && !sig.inputs.is_empty()
+        && sig.inputs.iter().all(|arg| match arg {
+            FnArg::Typed(pt) => matches!(&*pt.pat, syn::Pat::Ident(_)) && is_system_param(&pt.ty),
+            FnArg::Receiver(_) => false,
+        })
+}
+
+/// Params that have a `reborrow(&mut self) -> Self`
+#[cfg(feature = "hot_reload")]
+fn is_reborrowed_param(ty: &syn::Type) -> bool {
+    let syn::Type::Path(type_path) = ty else {
+        return false;
+    };
+    type_path
+        .path
+        .segments
+        .last()
+        .is_some_and(|last| last.ident == "Query" || last.ident == "Commands")
+}
+
+/// Expands `#[make_hot]`, `crate_found` is the path to ridiculous_bevy_hot_reloading
+#[cfg(feature = "hot_reload")]
+fn make_hot_impl(
+    attr: proc_macro2::TokenStream,
+    item: proc_macro2::TokenStream,
+    crate_found: proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let hot_args = HotArgs::parse(attr)?;
+    let ast: ItemFn = syn::parse2(item)?;
+    check_supported(&ast.sig, &hot_args)?;
+
+    let fn_name = &ast.sig.ident;
+
+    // The hash of the sig is appended onto the function name.
+    // An advantage of including the sig here is that it will crash
+    // is the user changes the sig.
+    // With `reset_locals` the types in `Local<T>` can change, so they are left out
+    let mut hashed_sig = ast.sig.clone();
+    if hot_args.reset_locals {
+        for arg in &mut hashed_sig.inputs {
+            if let FnArg::Typed(pt) = arg {
+                if let Some((local_path, _)) = split_local_type(&pt.ty) {
+                    *pt.ty = parse_quote! { #local_path<HotLocal> };
                 }
             }
         }
+    }
+    let hash = format!("{:016x}", stable_hash(hashed_sig.to_token_stream()));
+
+    // Compared between libraries on reload to tell which functions changed
+    let body_hash = stable_hash(ast.block.to_token_stream());
 
-        // Below deals with converting `mut commands: Commands` to `commands: &mut Commands`
-        for (idx, arg) in args_hot_func.iter_mut().enumerate() {
-            match arg.clone() {
-                FnArg::Receiver(_) => (),
-                FnArg::Typed(a) => match &*a.ty {
-                    syn::Type::Path(p) => {
-                        if p.path.segments.len() == 1 {
-                            if p.path.segments[0].ident == "Commands" {
-                                let name = &mut hot_arg_names[idx];
-                                let tok: TokenStream = quote! { #name : &mut Commands }.into();
-                                *arg = parse_macro_input!(tok as FnArg);
-                                *name = quote! {&mut #name};
-                                break;
-                            }
-                        } else {
-                            continue;
+    let mut args = Vec::new();
+    let mut args_hot_func = Vec::new();
+    let mut arg_names = Vec::new();
+    let mut arg_types = Vec::new();
+
+    let mut hot_arg_names = Vec::new();
+    let mut hot_arg_types = Vec::new();
+
+    // Systems take their params as `&mut T` in the hot function, so the params stay owned
+    // and dropped by the host. `Query` and `Commands` are reborrowed as values, so
+    // `&mut query` keeps working in the body.
+    // Bridge functions only take plain data and get it by value
+    let mut param_reborrows: Vec<syn::Stmt> = Vec::new();
+    for arg in &ast.sig.inputs {
+        match arg {
+            FnArg::Receiver(_) => {
+                args.push(arg.clone());
+                args_hot_func.push(arg.clone());
+            }
+            FnArg::Typed(pt) => {
+                let mut wrapper_arg = pt.clone();
+                let mut hot_arg = pt.clone();
+                if let syn::Pat::Ident(ref id) = *pt.pat {
+                    arg_names.push(id.ident.clone());
+                    let name = id.ident.clone();
+                    if hot_args.bridge {
+                        hot_arg_names.push(quote! { #name });
+                    } else {
+                        let ty = &pt.ty;
+                        hot_arg.ty = parse_quote! { &mut #ty };
+                        wrapper_arg.pat = parse_quote! { mut #name };
+                        hot_arg_names.push(quote! { &mut #name });
+                        if is_reborrowed_param(ty) {
+                            param_reborrows.push(parse_quote! {
+                                let mut #name = #name.reborrow();
+                            });
                         }
                     }
-                    _ => continue,
-                },
+                }
+                arg_types.push(pt.ty.clone());
+                args.push(FnArg::Typed(wrapper_arg));
+                args_hot_func.push(FnArg::Typed(hot_arg));
             }
         }
+    }
 
-        for arg in &args_hot_func {
-            match arg {
-                FnArg::Receiver(_) => (),
-                FnArg::Typed(a) => hot_arg_types.push(a.ty.clone()),
-            }
+    // Below deals with converting `counter: Local<T>` to `counter: Local<HotLocal>`.
+    // The value is created by the hot library, so it has the layout of the loaded build
+    let mut local_resets = Vec::new();
+    let mut local_inits: Vec<syn::Stmt> = Vec::new();
+    if hot_args.reset_locals {
+        for (idx, (arg, hot_arg)) in args.iter_mut().zip(&mut args_hot_func).enumerate() {
+            let FnArg::Typed(pt) = arg else { continue };
+            let syn::Pat::Ident(id) = &*pt.pat else {
+                continue;
+            };
+            let Some((local_path, local_type)) = split_local_type(&pt.ty) else {
+                continue;
+            };
+            let name = id.ident.clone();
+            *arg = parse_quote! { mut #name: #local_path<#crate_found::HotLocal> };
+            *hot_arg = parse_quote! { #name: &mut #crate_found::HotLocal };
+            hot_arg_names[idx] = quote! { &mut *#name };
+            local_resets.push(quote! {
+                #name.reset_if_reloaded(hot_target.version(), hot_target.library());
+            });
+            local_inits.push(parse_quote! {
+                let #name: &mut #local_type = #name.get_or_default::<#local_type>();
+            });
         }
+    }
 
-        let generics = &ast.sig.generics;
-        let where_clause = &ast.sig.generics.where_clause;
-        let fn_token = &ast.sig.fn_token;
-        let vis = &ast.vis;
+    for arg in &args_hot_func {
+        match arg {
+            FnArg::Receiver(_) => (),
+            FnArg::Typed(a) => hot_arg_types.push(a.ty.clone()),
+        }
+    }
 
-        let return_type = ast.sig.output;
+    let generics = &ast.sig.generics;
+    let where_clause = &ast.sig.generics.where_clause;
+    let fn_token = &ast.sig.fn_token;
+    let vis = &ast.vis;
 
-        let fn_name_orig_code_str = &format!("ridiculous_bevy_hot_{}_{}", fn_name, hash);
+    let return_type = ast.sig.output;
 
-        let fn_name_orig_code = &Ident::new(fn_name_orig_code_str, Span::call_site());
+    let fn_name_orig_code_str = &format!("ridiculous_bevy_hot_{}_{}", fn_name, hash);
 
-        let orig_stmts = ast.block.stmts;
+    // The exported name includes the module path, so functions with the same name
+    // and signature in different modules don't collide
+    let export_name = |suffix: &str| match &hot_args.name {
+        Some(name) => {
+            let symbol = format!("ridiculous_bevy_hot_{}_{}{}", name.value(), hash, suffix);
+            quote! { #symbol }
+        }
+        None => {
+            let symbol = format!("::{}_{}{}", fn_name, hash, suffix);
+            quote! { concat!("ridiculous_bevy_hot_", module_path!(), #symbol) }
+        }
+    };
+    let symbol = export_name("");
+    let body_hash_symbol = export_name("_body_hash");
 
-        let orig_func = quote! {
-            #[no_mangle] //#[allow(unused_mut)]
-            #vis #fn_token #fn_name_orig_code #generics( #(#args_hot_func),*) #return_type #where_clause {
-                #(#orig_stmts)*
-            }
-        };
+    let fn_name_orig_code = &Ident::new(fn_name_orig_code_str, Span::call_site());
+    let fn_name_body_hash = Ident::new(
+        &format!("{}_body_hash", fn_name_orig_code_str),
+        Span::call_site(),
+    );
+
+    let fn_name_str = fn_name.to_string();
 
-        let found_crate = crate_name("ridiculous_bevy_hot_reloading")
-            .expect("ridiculous_bevy_hot_reloading is present in `Cargo.toml`");
+    // The original block keeps its spans, so errors in the body point at the user's code
+    let mut orig_block = ast.block;
+    orig_block
+        .stmts
+        .splice(0..0, param_reborrows.into_iter().chain(local_inits));
 
-        let crate_found = match found_crate {
-            FoundCrate::Itself => quote!(crate),
-            FoundCrate::Name(name) => {
-                let ident = Ident::new(&name, Span::call_site());
-                quote!( #ident)
+    // The hot function is exported through a shim that takes all arguments as one pointer
+    // to a tuple of `&mut` references, so the exported signature doesn't depend on the
+    // arguments. The shim only reborrows them, nothing owned by the host is moved.
+    // Bridge functions are called from a differently built binary, so their shim is
+    // `extern "C"` and takes the arguments directly, rustc rejects types that aren't FFI-safe.
+    // Generic functions get one shim per type in `instances(..)`, the wrapper picks the
+    // shim by TypeId
+    let type_param = generics
+        .type_params()
+        .next()
+        .map(|param| param.ident.clone());
+    let mut shim_generics = generics.clone();
+    shim_generics.params = shim_generics
+        .params
+        .into_iter()
+        .filter(|param| matches!(param, syn::GenericParam::Lifetime(_)))
+        .collect();
+    shim_generics.where_clause = None;
+    let hot_arg_types = hot_arg_types
+        .iter()
+        .map(|ty| ty.to_token_stream())
+        .collect::<Vec<_>>();
+    let mut shims = Vec::new();
+    let mut instance_symbols = Vec::new();
+    let arg_indices = (0..arg_names.len())
+        .map(syn::Index::from)
+        .collect::<Vec<_>>();
+    let mut push_shim = |shim: Ident,
+                         shim_symbol: proc_macro2::TokenStream,
+                         turbofish: proc_macro2::TokenStream,
+                         arg_types: Vec<proc_macro2::TokenStream>,
+                         return_type: proc_macro2::TokenStream| {
+        if hot_args.bridge {
+            shims.push(quote! {
+                #[export_name = #shim_symbol]
+                #[doc(hidden)]
+                #[deny(improper_ctypes_definitions)]
+                #vis unsafe extern "C" fn #shim #shim_generics(#(#arg_names: #arg_types),*) #return_type {
+                    #fn_name_orig_code #turbofish(#(#arg_names),*)
+                }
+            });
+        } else {
+            shims.push(quote! {
+                #[export_name = #shim_symbol]
+                #[doc(hidden)]
+                #vis unsafe fn #shim #shim_generics(ctx: *mut ()) #return_type {
+                    let ctx = unsafe { &mut *(ctx as *mut (#(#arg_types,)*)) };
+                    #fn_name_orig_code #turbofish(#(&mut *ctx.#arg_indices),*)
+                }
+            });
+        }
+    };
+    match &type_param {
+        Some(type_param) => {
+            for instance in &hot_args.instances {
+                let instance_tokens = instance.to_token_stream();
+                let suffix = instance
+                    .segments
+                    .iter()
+                    .map(|segment| segment.ident.to_string())
+                    .collect::<Vec<_>>()
+                    .join("__");
+                let shim_symbol = export_name(&format!("__{}", suffix));
+                push_shim(
+                    Ident::new(
+                        &format!("{}__{}", fn_name_orig_code_str, suffix),
+                        Span::call_site(),
+                    ),
+                    shim_symbol.clone(),
+                    quote! { ::<#instance> },
+                    hot_arg_types
+                        .iter()
+                        .map(|ty| replace_ident(ty.clone(), type_param, &instance_tokens))
+                        .collect(),
+                    replace_ident(return_type.to_token_stream(), type_param, &instance_tokens),
+                );
+                instance_symbols.push(quote! {
+                    if type_id == std::any::TypeId::of::<#instance>() {
+                        #shim_symbol
+                    } else
+                });
             }
-        };
+        }
+        None => push_shim(
+            Ident::new(
+                &format!("{}_shim", fn_name_orig_code_str),
+                Span::call_site(),
+            ),
+            symbol.clone(),
+            quote! {},
+            hot_arg_types.clone(),
+            return_type.to_token_stream(),
+        ),
+    }
+
+    let orig_turbofish = type_param
+        .as_ref()
+        .map(|type_param| quote! { ::<#type_param> });
+
+    // Name of the function exported from the library
+    let hot_symbol = match &type_param {
+        Some(type_param) => quote! {
+            let hot_symbol: &str = {
+                let type_id = std::any::TypeId::of::<#type_param>();
+                #(#instance_symbols)* {
+                    panic!(
+                        "{} is not hot for {}, add it to `instances(..)`",
+                        #fn_name_str,
+                        std::any::type_name::<#type_param>()
+                    )
+                }
+            };
+        },
+        None => quote! {
+            let hot_symbol: &str = #symbol;
+        },
+    };
+
+    // TypeId needs the type parameter to be 'static
+    let mut wrapper_generics = generics.clone();
+    if let Some(type_param) = &type_param {
+        wrapper_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote! { #type_param: 'static });
+    }
+    let wrapper_where_clause = &wrapper_generics.where_clause;
 
-        let dyn_func = quote! {
+    let orig_func = quote! {
+        // `mut query: Query<..>` becomes `mut query: &mut Query<..>`
+        #[allow(unused_mut)]
+        #vis #fn_token #fn_name_orig_code #generics( #(#args_hot_func),*) #return_type #where_clause
+            #orig_block
+
+        #(#shims)*
+
+        #[export_name = #body_hash_symbol]
+        #[doc(hidden)]
+        #vis extern "C" fn #fn_name_body_hash() -> u64 {
+            #body_hash
+        }
+    };
+
+    // Identifies the function in the registry, the name alone can be ambiguous
+    let hot_fn_info = quote! {
+        #crate_found::HotFnInfo {
+            name: #fn_name_str,
+            path: concat!(module_path!(), "::", #fn_name_str),
+            symbol: #symbol,
+        }
+    };
+
+    // rerun_on_reload wraps the system in an exclusive system with the original name
+    let system_fn_name = if hot_args.rerun_on_reload {
+        Ident::new(&format!("{}_hot_system", fn_name), Span::call_site())
+    } else {
+        fn_name.clone()
+    };
+
+    let dyn_func = if hot_args.bridge {
+        quote! {
+            #vis #fn_token #fn_name #wrapper_generics( #(#args),* ) #return_type #wrapper_where_clause {
+                #hot_symbol
+                let Some(lib) = #crate_found::bridge_library() else {
+                    if #crate_found::hot_reload_disabled() {
+                        return #fn_name_orig_code #orig_turbofish(#(#hot_arg_names),*);
+                    }
+                    panic!("Hot reload library is None");
+                };
+                unsafe {
+                    // The library can be built differently from the host, so the shim uses the C ABI
+                    let func: #crate_found::libloading::Symbol<unsafe extern "C" fn (#(#hot_arg_types),*) #return_type> =
+                        lib.get(hot_symbol.as_bytes()).unwrap_or_else(|_| {
+                            panic!(
+                                "Can't find required function {}",
+                                hot_symbol
+                            )
+                        });
+                    func(#(#hot_arg_names),*)
+                }
+            }
+        }
+    } else {
+        quote! {
             #[allow(unused_mut)] // added because rust analyzer will complain about the mut on `mut query: Query<`
-            #vis #fn_token #fn_name #generics( #(#args),*,
-            hot_reload_lib_internal_use_only: Res<ridiculous_bevy_hot_reloading::HotReloadLibInternalUseOnly>) #return_type #where_clause {
-                if let Some(lib) = &hot_reload_lib_internal_use_only.library {
-                    unsafe {
-                        let func: #crate_found::libloading::Symbol<unsafe extern "C" fn (#(#hot_arg_types),*) #return_type , > =
-                            lib.get(#fn_name_orig_code_str.as_bytes()).unwrap_or_else(|_| {
+            #[allow(clippy::let_unit_value)]
+            #vis #fn_token #system_fn_name #wrapper_generics( #(#args,)*
+            hot_reload_lib_internal_use_only: Res<ridiculous_bevy_hot_reloading::HotReloadLibInternalUseOnly>,
+            hot_system_registry: Res<#crate_found::HotSystemRegistry>) #return_type #wrapper_where_clause {
+                let hot_target = hot_system_registry.target(#hot_fn_info, &hot_reload_lib_internal_use_only);
+                #(#local_resets)*
+                match hot_target {
+                    #crate_found::HotSystemTarget::Static => {
+                        return #fn_name_orig_code #orig_turbofish(#(#hot_arg_names),*);
+                    }
+                    #crate_found::HotSystemTarget::Library { library: lib, first_run, .. } => unsafe {
+                        #hot_symbol
+                        let func: #crate_found::libloading::Symbol<unsafe fn (*mut ()) #return_type> =
+                            lib.get(hot_symbol.as_bytes()).unwrap_or_else(|_| {
                                 panic!(
                                     "Can't find required function {}",
-                                    #fn_name_orig_code_str
+                                    hot_symbol
                                 )
                             });
-                        return func(#(#hot_arg_names),*);
-                    }
+                        // Time the first run from a new library, it includes lazy symbol resolution etc.
+                        let first_run_start = first_run.then(std::time::Instant::now);
+                        let mut ctx = (#(#hot_arg_names,)*);
+                        let result = func(&mut ctx as *mut _ as *mut ());
+                        if let Some(first_run_start) = first_run_start {
+                            hot_system_registry.record_first_run(#hot_fn_info, first_run_start.elapsed());
+                        }
+                        return result;
+                    },
+                    #crate_found::HotSystemTarget::NotLoaded => (),
                 }
                 panic!("Hot reload library is None");
-                //return #fn_name_orig_code(#(#hot_arg_names),*);
             }
-        };
+        }
+    };
+
+    let rerun_func = if hot_args.rerun_on_reload {
+        quote! {
+            #vis fn #fn_name(world: &mut World) {
+                #crate_found::run_rerun_on_reload(world, #hot_fn_info, #fn_name, |world| {
+                    world.run_system_cached(#system_fn_name)
+                });
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    Ok(quote! {
+        #orig_func
+        #dyn_func
+        #rerun_func
+
+        #crate_found::inventory::submit! { #hot_fn_info }
+    })
+}
+
+/// Rejects functions that can't be exported as a single `#[no_mangle]` function
+/// or called through a function pointer
+#[cfg(feature = "hot_reload")]
+fn check_supported(sig: &syn::Signature, hot_args: &HotArgs) -> syn::Result<()> {
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new_spanned(
+            asyncness,
+            "`make_hot` functions can't be async, spawn a task from a hot system instead",
+        ));
+    }
+    let mut generic_params = sig
+        .generics
+        .params
+        .iter()
+        .filter(|param| !matches!(param, syn::GenericParam::Lifetime(_)));
+    if hot_args.instances.is_empty() {
+        if let Some(param) = generic_params.next() {
+            return Err(syn::Error::new_spanned(
+                param,
+                "`make_hot` functions can't be generic, a `#[no_mangle]` function needs concrete types. \
+                Use `#[make_hot(instances(A, B))]` to export it for each type it is used with",
+            ));
+        }
+    } else {
+        match (generic_params.next(), generic_params.next()) {
+            (Some(syn::GenericParam::Type(_)), None) => (),
+            (None, _) => {
+                return Err(syn::Error::new_spanned(
+                    &sig.ident,
+                    "`instances` is for generic functions, this function has no type parameter",
+                ))
+            }
+            (Some(param), _) => {
+                return Err(syn::Error::new_spanned(
+                    param,
+                    "`instances` only supports functions with a single type parameter",
+                ))
+            }
+        }
+    }
+    for arg in &sig.inputs {
+        match arg {
+            FnArg::Receiver(receiver) => {
+                return Err(syn::Error::new_spanned(
+                    receiver,
+                    "`make_hot` can't be used on methods, move the body into a free function and call it from the method",
+                ));
+            }
+            FnArg::Typed(pt) => {
+                if !matches!(&*pt.pat, syn::Pat::Ident(_)) {
+                    return Err(syn::Error::new_spanned(
+                        &pt.pat,
+                        "`make_hot` arguments must be plain names, destructure them in the body instead",
+                    ));
+                }
+                if let Some(impl_trait) = find_impl_trait(&pt.ty) {
+                    return Err(syn::Error::new_spanned(
+                        impl_trait,
+                        "`make_hot` arguments can't be `impl Trait`, use a concrete type",
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "hot_reload")]
+fn find_impl_trait(ty: &syn::Type) -> Option<&syn::TypeImplTrait> {
+    match ty {
+        syn::Type::ImplTrait(impl_trait) => Some(impl_trait),
+        syn::Type::Reference(reference) => find_impl_trait(&reference.elem),
+        syn::Type::Paren(paren) => find_impl_trait(&paren.elem),
+        syn::Type::Group(group) => find_impl_trait(&group.elem),
+        syn::Type::Slice(slice) => find_impl_trait(&slice.elem),
+        syn::Type::Array(array) => find_impl_trait(&array.elem),
+        syn::Type::Ptr(ptr) => find_impl_trait(&ptr.elem),
+        syn::Type::Tuple(tuple) => tuple.elems.iter().find_map(find_impl_trait),
+        syn::Type::Path(path) => path.path.segments.iter().find_map(|segment| {
+            let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+                return None;
+            };
+            args.args.iter().find_map(|arg| match arg {
+                syn::GenericArgument::Type(ty) => find_impl_trait(ty),
+                _ => None,
+            })
+        }),
+        _ => None,
+    }
+}
+
+/// FNV-1a over the tokens, unlike `DefaultHasher` this is the same for every Rust version.
+/// Spacing is ignored so it doesn't depend on how the tokens are printed.
+#[cfg(feature = "hot_reload")]
+fn stable_hash(tokens: proc_macro2::TokenStream) -> u64 {
+    fn hash_tokens(tokens: proc_macro2::TokenStream, hash: &mut u64) {
+        for token in tokens {
+            let text = match token {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                    };
+                    hash_bytes(open.as_bytes(), hash);
+                    hash_tokens(group.stream(), hash);
+                    close.to_string()
+                }
+                token => token.to_string(),
+            };
+            hash_bytes(text.as_bytes(), hash);
+            // Separates tokens so `a b` and `ab` differ
+            hash_bytes(b" ", hash);
+        }
+    }
+    fn hash_bytes(bytes: &[u8], hash: &mut u64) {
+        for byte in bytes {
+            *hash ^= *byte as u64;
+            *hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    let mut hash = 0xcbf29ce484222325;
+    hash_tokens(tokens, &mut hash);
+    hash
+}
 
-        TokenStream::from(quote! {
-            #orig_func
-            #dyn_func
+/// Replaces every `from` ident in `tokens`, used to write out a type for each instance
+#[cfg(feature = "hot_reload")]
+fn replace_ident(
+    tokens: proc_macro2::TokenStream,
+    from: &Ident,
+    to: &proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|token| match token {
+            TokenTree::Ident(ident) if ident == *from => to.clone(),
+            TokenTree::Group(group) => {
+                let mut replaced =
+                    Group::new(group.delimiter(), replace_ident(group.stream(), from, to));
+                replaced.set_span(group.span());
+                TokenTree::Group(replaced).into()
+            }
+            token => token.into(),
         })
+        .collect()
+}
+
+/// Splits `Local<T>` into the path up to `Local` and `T`
+#[cfg(feature = "hot_reload")]
+fn split_local_type(ty: &syn::Type) -> Option<(syn::Path, syn::Type)> {
+    let syn::Type::Path(type_path) = ty else {
+        return None;
+    };
+    let mut path = type_path.path.clone();
+    let last = path.segments.last_mut()?;
+    if last.ident != "Local" {
+        return None;
     }
+    let syn::PathArguments::AngleBracketed(generic_args) = &last.arguments else {
+        return None;
+    };
+    let local_type = generic_args.args.iter().find_map(|arg| match arg {
+        syn::GenericArgument::Type(ty) => Some(ty.clone()),
+        _ => None,
+    })?;
+    last.arguments = syn::PathArguments::None;
+    Some((path, local_type))
 }
@@ -0,0 +1,246 @@
+use quote::quote;
+
+use crate::{hot_module_impl, make_hot_impl};
+
+/// Expands `#[make_hot(attr)] item` and formats the output for snapshots
+fn expand(attr: proc_macro2::TokenStream, item: proc_macro2::TokenStream) -> String {
+    let tokens = make_hot_impl(attr, item, quote!(ridiculous_bevy_hot_reloading))
+        .unwrap_or_else(|e| panic!("make_hot failed: {}", e));
+    let file = syn::parse2(tokens).expect("make_hot output is not valid rust");
+    prettyplease::unparse(&file)
+}
+
+fn expand_module(item: proc_macro2::TokenStream) -> String {
+    let tokens = hot_module_impl(quote!(), item, quote!(ridiculous_bevy_hot_reloading))
+        .unwrap_or_else(|e| panic!("hot_module failed: {}", e));
+    let file = syn::parse2(tokens).expect("hot_module output is not valid rust");
+    prettyplease::unparse(&file)
+}
+
+fn expand_err(attr: proc_macro2::TokenStream, item: proc_macro2::TokenStream) -> String {
+    match make_hot_impl(attr, item, quote!(ridiculous_bevy_hot_reloading)) {
+        Ok(_) => panic!("expected make_hot to fail"),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[test]
+fn system() {
+    insta::assert_snapshot!(expand(
+        quote!(),
+        quote! {
+            pub fn rotate(mut query: Query<&mut Transform, With<Shape>>, time: Res<Time>) {
+                for mut transform in &mut query {
+                    transform.rotate_y(time.delta_secs());
+                }
+            }
+        }
+    ));
+}
+
+#[test]
+fn commands() {
+    insta::assert_snapshot!(expand(
+        quote!(),
+        quote! {
+            fn spawn(mut commands: Commands, time: Res<Time>) {
+                commands.spawn(Name::new("spawned"));
+            }
+        }
+    ));
+}
+
+#[test]
+fn no_params() {
+    insta::assert_snapshot!(expand(
+        quote!(),
+        quote! {
+            fn log_frame() {
+                info!("frame");
+            }
+        }
+    ));
+}
+
+#[test]
+fn return_type_and_where_clause() {
+    insta::assert_snapshot!(expand(
+        quote!(),
+        quote! {
+            fn should_run(time: Res<Time>) -> bool where Time: Send {
+                time.elapsed_secs() > 1.0
+            }
+        }
+    ));
+}
+
+#[test]
+fn bridge() {
+    insta::assert_snapshot!(expand(
+        quote!(bridge),
+        quote! {
+            pub fn spin_speed(time: f32) -> f32 {
+                time.sin() * 2.0
+            }
+        }
+    ));
+}
+
+#[test]
+fn reset_locals() {
+    insta::assert_snapshot!(expand(
+        quote!(reset_locals),
+        quote! {
+            fn count(mut counter: Local<u32>, time: Res<Time>) {
+                *counter += 1;
+            }
+        }
+    ));
+}
+
+#[test]
+fn reset_locals_symbol_ignores_local_type() {
+    let symbol = |local_type: proc_macro2::TokenStream| {
+        let expanded = expand(
+            quote!(reset_locals),
+            quote! {
+                fn count(mut counter: Local<#local_type>) {}
+            },
+        );
+        let start = expanded.find("::count_").expect("no exported symbol");
+        expanded[start..start + "::count_".len() + 16].to_string()
+    };
+    assert_eq!(symbol(quote!(u32)), symbol(quote!(String)));
+}
+
+#[test]
+fn rerun_on_reload() {
+    insta::assert_snapshot!(expand(
+        quote!(rerun_on_reload),
+        quote! {
+            pub fn setup(mut commands: Commands) {
+                commands.spawn(Camera3d::default());
+            }
+        }
+    ));
+}
+
+#[test]
+fn instances() {
+    insta::assert_snapshot!(expand(
+        quote!(instances(Goblin, enemies::Orc)),
+        quote! {
+            fn spawn_enemy<T: Component + Default>(mut commands: Commands, spawner: Query<&Transform, With<T>>) {
+                commands.spawn(T::default());
+            }
+        }
+    ));
+}
+
+#[test]
+fn name() {
+    insta::assert_snapshot!(expand(
+        quote!(bridge, name = "player::speed"),
+        quote! {
+            pub fn speed(time: f32) -> f32 {
+                time * 2.0
+            }
+        }
+    ));
+}
+
+#[test]
+fn symbols_are_stable() {
+    // Same tokens with different spacing give the same symbols
+    let item = expand(
+        quote!(),
+        quote!(
+            fn speed(time: Res<Time>) -> f32 {
+                1.0
+            }
+        ),
+    );
+    let spaced = expand(
+        quote!(),
+        "fn  speed ( time :Res< Time > )->f32{1.0}".parse().unwrap(),
+    );
+    assert_eq!(item, spaced);
+}
+
+#[test]
+fn unknown_option() {
+    assert_eq!(
+        expand_err(
+            quote!(fast),
+            quote!(
+                fn rotate() {}
+            )
+        ),
+        "unknown make_hot option, expected `bridge`, `reset_locals`, `rerun_on_reload`, `instances(..)` or `name = \"..\"`"
+    );
+}
+
+#[test]
+fn bridge_with_system_options() {
+    assert_eq!(
+        expand_err(
+            quote!(bridge, reset_locals),
+            quote!(
+                fn speed() -> f32 {
+                    1.0
+                }
+            )
+        ),
+        "`reset_locals` can't be used with `bridge`, bridge functions are not systems"
+    );
+}
+
+#[test]
+fn hot_module() {
+    insta::assert_snapshot!(expand_module(quote! {
+        mod gameplay {
+            use bevy::prelude::*;
+
+            pub fn rotate(mut query: Query<&mut Transform>, time: Option<Res<Time>>) {}
+
+            #[not_hot]
+            pub fn count(mut frames: Local<u32>) {}
+
+            #[make_hot(reset_locals)]
+            pub fn count_hot(mut frames: Local<u32>) {}
+
+            pub fn speed(time: f32) -> f32 {
+                time * 2.0
+            }
+
+            fn private(mut commands: Commands) {}
+        }
+    }));
+}
+
+#[test]
+fn hot_module_errors() {
+    let err = |attr, item| match hot_module_impl(attr, item, quote!(ridiculous_bevy_hot_reloading))
+    {
+        Ok(_) => panic!("expected hot_module to fail"),
+        Err(e) => e.to_string(),
+    };
+    assert_eq!(
+        err(
+            quote!(),
+            quote!(
+                mod gameplay;
+            )
+        ),
+        "`hot_module` needs an inline module, `mod name { .. }`"
+    );
+    assert_eq!(
+        err(
+            quote!(bridge),
+            quote!(
+                mod gameplay {}
+            )
+        ),
+        "`hot_module` takes no options"
+    );
+}
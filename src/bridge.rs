@@ -0,0 +1,34 @@
+//! Access to the hot library from outside of systems.
+//!
+//! In bridge mode the host statically links bevy and the hot library has its own copy,
+//! so TypeIds differ between them. `#[make_hot(bridge)]` functions only take plain data
+//! and find the library through here instead of through a system param.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, RwLock,
+};
+
+use libloading::Library;
+
+static BRIDGE_LIBRARY: RwLock<Option<Arc<Library>>> = RwLock::new(None);
+static HOT_RELOAD_DISABLED: AtomicBool = AtomicBool::new(false);
+
+/// The currently loaded hot library, if any
+pub fn bridge_library() -> Option<Arc<Library>> {
+    BRIDGE_LIBRARY.read().unwrap().clone()
+}
+
+pub(crate) fn set_bridge_library(library: Option<Arc<Library>>) {
+    *BRIDGE_LIBRARY.write().unwrap() = library;
+}
+
+/// Set when the plugin is disabled with `HotReloadPlugin::disabled` or `HOT_RELOAD_DISABLE`,
+/// `#[make_hot(bridge)]` functions then call the version linked into the host
+pub fn hot_reload_disabled() -> bool {
+    HOT_RELOAD_DISABLED.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_hot_reload_disabled(disabled: bool) {
+    HOT_RELOAD_DISABLED.store(disabled, Ordering::Relaxed);
+}
@@ -0,0 +1,108 @@
+use bevy::{ecs::system::SystemId, prelude::*};
+
+use crate::registry::hot_fns;
+
+/// Names of one or more `#[make_hot]` functions, see [`HotReloadAppExt::on_hot_reload`]
+pub trait HotFnNames {
+    fn into_names(self) -> Vec<String>;
+}
+
+impl HotFnNames for &str {
+    fn into_names(self) -> Vec<String> {
+        vec![self.to_string()]
+    }
+}
+
+impl HotFnNames for String {
+    fn into_names(self) -> Vec<String> {
+        vec![self]
+    }
+}
+
+impl<const N: usize> HotFnNames for [&str; N] {
+    fn into_names(self) -> Vec<String> {
+        self.iter().map(|name| name.to_string()).collect()
+    }
+}
+
+impl HotFnNames for &[&str] {
+    fn into_names(self) -> Vec<String> {
+        self.iter().map(|name| name.to_string()).collect()
+    }
+}
+
+impl HotFnNames for Vec<&str> {
+    fn into_names(self) -> Vec<String> {
+        self.as_slice().into_names()
+    }
+}
+
+/// Systems registered with [`HotReloadAppExt::on_hot_reload`],
+/// with the symbols of the functions they are run for
+#[derive(Resource, Default)]
+pub struct HotReloadCallbacks {
+    callbacks: Vec<(Vec<&'static str>, SystemId)>,
+}
+
+pub trait HotReloadAppExt {
+    /// Runs `system` once after every reload that changed any of the named hot functions.
+    /// E.g. `app.on_hot_reload("generate_level", spawn_level)` to run a `Startup`
+    /// system again when the generator is edited.
+    /// A name applies to every hot function with that name, use a path like
+    /// `"my_game::level::generate_level"` to pick one.
+    fn on_hot_reload<M>(
+        &mut self,
+        names: impl HotFnNames,
+        system: impl IntoSystem<(), (), M> + 'static,
+    ) -> &mut Self;
+}
+
+impl HotReloadAppExt for App {
+    fn on_hot_reload<M>(
+        &mut self,
+        names: impl HotFnNames,
+        system: impl IntoSystem<(), (), M> + 'static,
+    ) -> &mut Self {
+        let names = names.into_names();
+        let hot_fns = hot_fns();
+        for name in &names {
+            if !hot_fns.iter().any(|info| info.matches(name)) {
+                warn!("on_hot_reload: there is no #[make_hot] function {}", name);
+            }
+        }
+        let symbols = hot_fns
+            .iter()
+            .filter(|info| names.iter().any(|name| info.matches(name)))
+            .map(|info| info.symbol)
+            .collect();
+        let system_id = self.world_mut().register_system(system);
+        self.init_resource::<HotReloadCallbacks>()
+            .world_mut()
+            .resource_mut::<HotReloadCallbacks>()
+            .callbacks
+            .push((symbols, system_id));
+        self
+    }
+}
+
+/// Runs each callback at most once per reload, even if it listens to several functions
+#[cfg(feature = "hot_reload")]
+pub(crate) fn run_reload_callbacks(
+    hot_reload_int: Res<crate::HotReloadLibInternalUseOnly>,
+    mut events: EventReader<crate::HotReloadEvent>,
+    callbacks: Res<HotReloadCallbacks>,
+    mut commands: Commands,
+) {
+    let changed = events
+        .read()
+        .flat_map(|event| event.changed.iter().map(|info| info.symbol))
+        .collect::<Vec<_>>();
+    if !hot_reload_int.updated_this_frame {
+        return;
+    }
+    for (symbols, system_id) in &callbacks.callbacks {
+        if symbols.iter().any(|symbol| changed.contains(symbol)) {
+            commands.run_system(*system_id);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::ffi::OsString;
+
+use libloading::{Library, Symbol};
+
+use crate::{
+    restart::{
+        restart_count, RESTART_COUNT_ENV, RESTART_REQUESTED_SYMBOL, RESTART_STATE_ENV,
+        SET_RESTORED_STATE_SYMBOL, TAKE_RESTART_REQUEST_SYMBOL,
+    },
+    HotReloadError, LibPathSet, RestartRequest,
+};
+
+/// Passed to the library entry point of a [`DynMainBuilder::with_args`] builder
+#[derive(Debug, Clone, Default)]
+pub struct DynMainArgs {
+    pub args: Vec<String>,
+    pub config: Option<String>,
+}
+
+/// Copies the library before running its main function so the original can be overwritten.
+/// If the app requests a restart with [`crate::request_restart`] or
+/// `HotReloadCommand::Restart`, the executable is run again with the same args in a new
+/// process, which loads the fresh build. [`DynMainBuilder::run`] then returns its exit code.
+/// ```no_run
+/// # use ridiculous_bevy_hot_reloading::DynMainBuilder;
+/// # use ridiculous_bevy_hot_reloading::DynMainArgs;
+/// let main_args = DynMainArgs {
+///     args: std::env::args().collect(),
+///     config: None,
+/// };
+/// let exit_code = DynMainBuilder::with_args("main", main_args)
+///     .run()
+///     .unwrap_or_else(|e| panic!("{}", e));
+/// std::process::exit(exit_code);
+/// ```
+pub struct DynMainBuilder {
+    main_function_name: String,
+    library_name: Option<String>,
+    main_args: Option<DynMainArgs>,
+    remove_copy: bool,
+}
+
+impl DynMainBuilder {
+    /// `main_function_name` must be exported from the library with `#[no_mangle]`
+    /// and be `fn()`.
+    pub fn new(main_function_name: impl Into<String>) -> Self {
+        DynMainBuilder {
+            main_function_name: main_function_name.into(),
+            library_name: None,
+            main_args: None,
+            remove_copy: true,
+        }
+    }
+
+    /// `main_function_name` must be exported from the library with `#[no_mangle]`
+    /// and be `fn(&DynMainArgs) -> i32`. `main_args` is passed to it on every run.
+    pub fn with_args(main_function_name: impl Into<String>, main_args: DynMainArgs) -> Self {
+        DynMainBuilder {
+            main_args: Some(main_args),
+            ..DynMainBuilder::new(main_function_name)
+        }
+    }
+
+    /// The name of the library target in Cargo.toml, see `HotReloadPlugin::library_name`
+    pub fn library_name(mut self, library_name: impl Into<String>) -> Self {
+        self.library_name = Some(library_name.into());
+        self
+    }
+
+    /// Remove the `_main_in_use` copy after the entry point returns. Defaults to true.
+    pub fn remove_copy(mut self, remove_copy: bool) -> Self {
+        self.remove_copy = remove_copy;
+        self
+    }
+
+    /// Load the library and run the entry point. If it requests a restart, the executable is
+    /// run again: on unix it replaces this process, elsewhere the first process runs each
+    /// restart as a child and returns the exit code of the last one.
+    /// Returns the exit code from the entry point, or 0 for [`DynMainBuilder::new`] entry points.
+    pub fn run(self) -> Result<i32, HotReloadError> {
+        let lib_paths =
+            LibPathSet::new(self.library_name.clone()).map_err(HotReloadError::CurrentExe)?;
+        let restart = restart_count();
+        if restart == 0 && self.remove_copy {
+            // Left behind if a previous run was killed
+            lib_paths.remove_main_in_use_copies();
+        }
+        let restored_state = take_restart_state_file();
+        let (exit_code, request) = self.run_once(&lib_paths, restart, restored_state)?;
+        let Some(request) = request else {
+            return Ok(exit_code);
+        };
+        if let Some(build_args) = &request.build_args {
+            rebuild(build_args);
+        }
+        println!("Restarting {}", lib_paths.lib_file_path().to_string_lossy());
+        restart_process(&lib_paths, restart + 1, request.state)
+    }
+
+    fn run_once(
+        &self,
+        lib_paths: &LibPathSet,
+        restart: u32,
+        restored_state: Option<Vec<u8>>,
+    ) -> Result<(i32, Option<RestartRequest>), HotReloadError> {
+        let lib_file_path = lib_paths.lib_file_path();
+        // Each run gets its own copy, the previous library may not actually be unloaded
+        let main_in_use_file_path = lib_paths.main_in_use_file_path(restart);
+
+        if !lib_file_path.is_file() {
+            return Err(HotReloadError::LibraryNotFound(lib_file_path));
+        }
+        std::fs::copy(&lib_file_path, &main_in_use_file_path).map_err(|source| {
+            HotReloadError::Copy {
+                from: lib_file_path,
+                to: main_in_use_file_path.clone(),
+                source,
+            }
+        })?;
+
+        let result = unsafe {
+            Library::new(&main_in_use_file_path)
+                .map_err(|source| HotReloadError::Load {
+                    path: main_in_use_file_path.clone(),
+                    source,
+                })
+                .and_then(|lib| {
+                    if let Ok(set_state) =
+                        lib.get::<fn(Option<Vec<u8>>)>(SET_RESTORED_STATE_SYMBOL.as_bytes())
+                    {
+                        set_state(restored_state);
+                    }
+                    let exit_code = call_main(&lib, &self.main_function_name, &self.main_args)?;
+                    Ok((exit_code, take_restart_request(&lib)))
+                })
+        };
+
+        if self.remove_copy {
+            // The library is closed by now, the copy is stale
+            let _ = std::fs::remove_file(main_in_use_file_path);
+        }
+        result
+    }
+}
+
+/// Libraries built against an older version of this crate don't export these
+unsafe fn take_restart_request(lib: &Library) -> Option<RestartRequest> {
+    let requested = lib
+        .get::<fn() -> bool>(RESTART_REQUESTED_SYMBOL.as_bytes())
+        .ok()?;
+    if !requested() {
+        return None;
+    }
+    let take = lib
+        .get::<fn() -> Option<RestartRequest>>(TAKE_RESTART_REQUEST_SYMBOL.as_bytes())
+        .ok()?;
+    Some(take().unwrap_or_default())
+}
+
+/// Runs the current executable again with the same args in place of this process.
+/// Windowing and other process wide state can't be set up twice in one process.
+#[cfg(unix)]
+fn restart_process(
+    lib_paths: &LibPathSet,
+    restart: u32,
+    state: Option<Vec<u8>>,
+) -> Result<i32, HotReloadError> {
+    use std::{io::Write, os::unix::process::CommandExt};
+
+    write_restart_state_file(lib_paths, state)?;
+    let mut command = restart_command(lib_paths, restart)?;
+    let _ = std::io::stdout().flush();
+    // Only returns if the process could not be replaced
+    Err(HotReloadError::Restart(command.exec()))
+}
+
+/// Without `exec` the first process runs each restart as a child and waits for it.
+/// A child that restarts exits with [`RESTART_EXIT_CODE`], so processes don't nest.
+#[cfg(not(unix))]
+fn restart_process(
+    lib_paths: &LibPathSet,
+    mut restart: u32,
+    state: Option<Vec<u8>>,
+) -> Result<i32, HotReloadError> {
+    write_restart_state_file(lib_paths, state)?;
+    if restart_count() > 0 {
+        std::process::exit(RESTART_EXIT_CODE);
+    }
+    loop {
+        let status = restart_command(lib_paths, restart)?
+            .status()
+            .map_err(HotReloadError::Restart)?;
+        match status.code() {
+            // The child wrote the state for the next one
+            Some(RESTART_EXIT_CODE) => restart += 1,
+            // Killed by a signal
+            code => return Ok(code.unwrap_or(1)),
+        }
+    }
+}
+
+/// Exit code of a restarted child that wants to be restarted again
+#[cfg(not(unix))]
+const RESTART_EXIT_CODE: i32 = 0x5245;
+
+fn restart_command(
+    lib_paths: &LibPathSet,
+    restart: u32,
+) -> Result<std::process::Command, HotReloadError> {
+    let exe = std::env::current_exe().map_err(HotReloadError::CurrentExe)?;
+    let mut command = std::process::Command::new(exe);
+    command
+        .args(std::env::args_os().skip(1))
+        .env(RESTART_COUNT_ENV, restart.to_string());
+    let path = lib_paths.restart_state_file_path();
+    if path.exists() {
+        command.env(RESTART_STATE_ENV, path);
+    } else {
+        command.env_remove(RESTART_STATE_ENV);
+    }
+    Ok(command)
+}
+
+/// Read back by [`take_restart_state_file`] in the restarted process
+fn write_restart_state_file(
+    lib_paths: &LibPathSet,
+    state: Option<Vec<u8>>,
+) -> Result<(), HotReloadError> {
+    let path = lib_paths.restart_state_file_path();
+    match state {
+        Some(state) => std::fs::write(&path, state).map_err(HotReloadError::Restart),
+        None => {
+            let _ = std::fs::remove_file(&path);
+            Ok(())
+        }
+    }
+}
+
+/// State written by [`restart_process`] in the previous process
+fn take_restart_state_file() -> Option<Vec<u8>> {
+    let path = std::env::var_os(RESTART_STATE_ENV)?;
+    let state = std::fs::read(&path).ok()?;
+    let _ = std::fs::remove_file(&path);
+    Some(state)
+}
+
+/// The app exiting also stops cargo watch, so build once more to make sure the library is fresh
+fn rebuild(build_args: &[OsString]) {
+    match std::process::Command::new("cargo")
+        .args(build_args)
+        .status()
+    {
+        Ok(status) if status.success() => (),
+        Ok(status) => eprintln!("cargo build failed: {}, restarting with last build", status),
+        Err(e) => eprintln!(
+            "Could not start cargo build: {}, restarting with last build",
+            e
+        ),
+    }
+}
+
+unsafe fn call_main(
+    lib: &Library,
+    main_function_name: &str,
+    main_args: &Option<DynMainArgs>,
+) -> Result<i32, HotReloadError> {
+    let missing_symbol = |source| HotReloadError::MissingSymbol {
+        name: main_function_name.to_string(),
+        source,
+    };
+    // The signature is picked by the constructor, `new` or `with_args`
+    match main_args {
+        Some(main_args) => {
+            let func: Symbol<fn(&DynMainArgs) -> i32> = lib
+                .get(main_function_name.as_bytes())
+                .map_err(missing_symbol)?;
+            Ok(func(main_args))
+        }
+        None => {
+            let func: Symbol<fn()> = lib
+                .get(main_function_name.as_bytes())
+                .map_err(missing_symbol)?;
+            func();
+            Ok(0)
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{fmt, path::PathBuf};
+
+/// Why the library could not be found, copied or loaded
+#[derive(Debug)]
+pub enum HotReloadError {
+    /// The target dir is found from the path of the current executable
+    CurrentExe(std::io::Error),
+    /// The library has not been built yet, or `library_name` is wrong
+    LibraryNotFound(PathBuf),
+    /// The library could not be copied to its `_in_use` path
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        source: std::io::Error,
+    },
+    /// `Library::new` failed
+    Load {
+        path: PathBuf,
+        source: libloading::Error,
+    },
+    /// The library doesn't export the requested function
+    MissingSymbol {
+        name: String,
+        source: libloading::Error,
+    },
+    /// The restart state could not be written or the executable could not be started again
+    Restart(std::io::Error),
+}
+
+impl fmt::Display for HotReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HotReloadError::CurrentExe(e) => {
+                write!(f, "could not get the path of the current executable: {}", e)
+            }
+            HotReloadError::LibraryNotFound(path) => {
+                write!(f, "could not find library file {:?}", path)
+            }
+            HotReloadError::Copy { from, to, source } => {
+                write!(f, "could not copy {:?} to {:?}: {}", from, to, source)
+            }
+            HotReloadError::Load { path, source } => {
+                write!(f, "could not open library {:?}: {}", path, source)
+            }
+            HotReloadError::MissingSymbol { name, source } => {
+                write!(f, "could not find function {}: {}", name, source)
+            }
+            HotReloadError::Restart(e) => write!(f, "could not restart the app: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HotReloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HotReloadError::CurrentExe(e) => Some(e),
+            HotReloadError::LibraryNotFound(_) => None,
+            HotReloadError::Copy { source, .. } => Some(source),
+            HotReloadError::Load { source, .. } => Some(source),
+            HotReloadError::MissingSymbol { source, .. } => Some(source),
+            HotReloadError::Restart(e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Statics that the hot library needs to share with the host.
+//!
+//! Without `bevy/dynamic_linking` the hot library contains its own copies of bevy's
+//! statics, so logging would go nowhere. The host and the library can be built with
+//! different compilers and flags, so only `#[repr(C)]` data and `extern "C"` functions
+//! cross the boundary.
+//!
+//! Bevy's task pools can't be passed over the C ABI, so the library creates its own
+//! with the host's thread counts. Their threads run code from the library, so a library
+//! that created task pools is never unloaded.
+
+use bevy::{
+    log::{
+        tracing::{dispatcher, field::Field, Dispatch, Event, Level, Subscriber},
+        tracing_subscriber::{
+            field::Visit, filter::LevelFilter, layer::Context, layer::SubscriberExt, Layer,
+            Registry,
+        },
+    },
+    tasks::{AsyncComputeTaskPool, ComputeTaskPool, IoTaskPool, TaskPool, TaskPoolBuilder},
+};
+use std::fmt::Write;
+
+/// Name of the entry point exported from every library that includes this crate
+pub const HOT_INIT_SYMBOL: &str = "ridiculous_bevy_hot_init";
+
+/// Signature of [`ridiculous_bevy_hot_init`]
+pub type HotInit = unsafe extern "C" fn(globals: *const HotGlobals) -> bool;
+
+/// Writes one record to the host's logger. Level is 1 (error) to 5 (trace).
+///
+/// # Safety
+/// `target` and `message` must point to `target_len` and `message_len` bytes of valid utf-8
+/// that stay valid for the duration of the call.
+pub type HotLogFn = unsafe extern "C" fn(
+    level: u8,
+    target: *const u8,
+    target_len: usize,
+    message: *const u8,
+    message_len: usize,
+);
+
+/// Globals the host passes to the hot library after each load.
+#[repr(C)]
+pub struct HotGlobals {
+    pub log: HotLogFn,
+    /// Most verbose level the host logs, 0 (off) to 5 (trace)
+    pub max_log_level: u8,
+    /// Threads of the host's task pools, 0 when the host hasn't created that pool
+    pub compute_threads: usize,
+    pub async_compute_threads: usize,
+    pub io_threads: usize,
+}
+
+impl HotGlobals {
+    /// Collect the globals of the calling side
+    pub fn from_host() -> Self {
+        HotGlobals {
+            log: host_log,
+            max_log_level: log::max_level() as u8,
+            compute_threads: ComputeTaskPool::try_get().map_or(0, |pool| pool.thread_num()),
+            async_compute_threads: AsyncComputeTaskPool::try_get()
+                .map_or(0, |pool| pool.thread_num()),
+            io_threads: IoTaskPool::try_get().map_or(0, |pool| pool.thread_num()),
+        }
+    }
+}
+
+unsafe extern "C" fn host_log(
+    level: u8,
+    target: *const u8,
+    target_len: usize,
+    message: *const u8,
+    message_len: usize,
+) {
+    let Some(level) = level_from_u8(level) else {
+        return;
+    };
+    // SAFETY: guaranteed by the caller, see `HotLogFn`
+    let (target, message) = unsafe {
+        (
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(target, target_len)),
+            std::str::from_utf8_unchecked(std::slice::from_raw_parts(message, message_len)),
+        )
+    };
+    // Bevy's LogPlugin forwards `log` records to tracing
+    log::logger().log(
+        &log::Record::builder()
+            .level(level)
+            .target(target)
+            .args(format_args!("{message}"))
+            .build(),
+    );
+}
+
+fn level_from_u8(level: u8) -> Option<log::Level> {
+    Some(match level {
+        1 => log::Level::Error,
+        2 => log::Level::Warn,
+        3 => log::Level::Info,
+        4 => log::Level::Debug,
+        5 => log::Level::Trace,
+        _ => return None,
+    })
+}
+
+/// Called by the host on the hot library after it is loaded.
+/// Globals that are already set, like when using bevy_dylib, are left as they are.
+/// Returns true when task pools were created, the library must then stay loaded.
+///
+/// # Safety
+/// `globals` must point to a valid [`HotGlobals`] for the duration of the call.
+#[no_mangle]
+pub unsafe extern "C" fn ridiculous_bevy_hot_init(globals: *const HotGlobals) -> bool {
+    let globals = unsafe { &*globals };
+    let max_level = level_from_u8(globals.max_log_level);
+    let filter = match max_level {
+        Some(level) => LevelFilter::from_level(tracing_level(level)),
+        None => LevelFilter::OFF,
+    };
+    let subscriber = Registry::default()
+        .with(filter)
+        .with(ForwardLayer(globals.log));
+    let _ = dispatcher::set_global_default(Dispatch::new(subscriber));
+    if log::set_boxed_logger(Box::new(ForwardLog(globals.log))).is_ok() {
+        log::set_max_level(
+            max_level.map_or(log::LevelFilter::Off, |level| level.to_level_filter()),
+        );
+    }
+
+    // Each pool is its own static and can be missing on its own
+    let mut created = false;
+    if globals.compute_threads > 0 && ComputeTaskPool::try_get().is_none() {
+        ComputeTaskPool::get_or_init(|| task_pool(globals.compute_threads, "Compute"));
+        created = true;
+    }
+    if globals.async_compute_threads > 0 && AsyncComputeTaskPool::try_get().is_none() {
+        AsyncComputeTaskPool::get_or_init(|| {
+            task_pool(globals.async_compute_threads, "Async Compute")
+        });
+        created = true;
+    }
+    if globals.io_threads > 0 && IoTaskPool::try_get().is_none() {
+        IoTaskPool::get_or_init(|| task_pool(globals.io_threads, "IO"));
+        created = true;
+    }
+    created
+}
+
+fn task_pool(threads: usize, name: &str) -> TaskPool {
+    TaskPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(format!("Hot {name} Task Pool"))
+        .build()
+}
+
+fn tracing_level(level: log::Level) -> Level {
+    match level {
+        log::Level::Error => Level::ERROR,
+        log::Level::Warn => Level::WARN,
+        log::Level::Info => Level::INFO,
+        log::Level::Debug => Level::DEBUG,
+        log::Level::Trace => Level::TRACE,
+    }
+}
+
+fn forward(log: HotLogFn, level: log::Level, target: &str, message: &str) {
+    // SAFETY: both are valid utf-8 and borrowed for the whole call
+    unsafe {
+        log(
+            level as u8,
+            target.as_ptr(),
+            target.len(),
+            message.as_ptr(),
+            message.len(),
+        )
+    };
+}
+
+/// `log` logger of the library, sends records to the host
+struct ForwardLog(HotLogFn);
+
+impl log::Log for ForwardLog {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            forward(
+                self.0,
+                record.level(),
+                record.target(),
+                &record.args().to_string(),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Tracing layer of the library, formats events and sends them to the host
+struct ForwardLayer(HotLogFn);
+
+impl<S: Subscriber> Layer<S> for ForwardLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = match *metadata.level() {
+            Level::ERROR => log::Level::Error,
+            Level::WARN => log::Level::Warn,
+            Level::INFO => log::Level::Info,
+            Level::DEBUG => log::Level::Debug,
+            Level::TRACE => log::Level::Trace,
+        };
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        forward(self.0, level, metadata.target(), &message.0);
+    }
+}
+
+/// Formats like the default fmt layer, the message first and then `field=value`
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, "{}={value:?}", field.name());
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub const HOT_RELOAD_HISTORY_FILE: &str = "hot_reload_history.jsonl";
+
+/// One line of `hot_reload_history.jsonl`, written for every build and load
+#[derive(Debug, Clone, Default)]
+pub struct HotReloadRecord {
+    /// Milliseconds since the unix epoch
+    pub timestamp_ms: u128,
+    /// Version of the `_hot_in_use` copy, 0 for builds that were not loaded
+    pub library_version: u64,
+    /// FNV-1a hash of the library file, to match a report with the exact build
+    pub library_hash: Option<u64>,
+    /// Hot functions whose body changed since the previous library
+    pub changed: Vec<String>,
+    pub build_time: Option<Duration>,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl HotReloadRecord {
+    pub fn now() -> Self {
+        HotReloadRecord {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_millis())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let changed = self
+            .changed
+            .iter()
+            .map(|name| json_string(name))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{{\"timestamp_ms\":{},\"library_version\":{},\"library_hash\":{},\"changed\":[{}],\"build_time_ms\":{},\"success\":{},\"error\":{}}}",
+            self.timestamp_ms,
+            self.library_version,
+            self.library_hash
+                .map_or("null".to_string(), |hash| json_string(&format!("{:016x}", hash))),
+            changed,
+            self.build_time
+                .map_or("null".to_string(), |time| time.as_millis().to_string()),
+            self.success,
+            self.error
+                .as_deref()
+                .map_or("null".to_string(), json_string),
+        )
+    }
+}
+
+/// Appends [`HotReloadRecord`]s to `hot_reload_history.jsonl` next to the library,
+/// keeping at most `max_entries` lines
+#[derive(Debug, Clone)]
+pub struct HotReloadHistory {
+    path: PathBuf,
+    max_entries: usize,
+}
+
+impl HotReloadHistory {
+    pub fn new(folder: &Path, max_entries: usize) -> Self {
+        HotReloadHistory {
+            path: folder.join(HOT_RELOAD_HISTORY_FILE),
+            max_entries,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Does nothing when `max_entries` is 0
+    pub fn append(&self, record: &HotReloadRecord) -> std::io::Result<()> {
+        if self.max_entries == 0 {
+            return Ok(());
+        }
+        let mut lines = match std::fs::File::open(&self.path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .collect::<Result<Vec<_>, _>>()?,
+            Err(_) => Vec::new(),
+        };
+        if lines.len() < self.max_entries {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            return writeln!(file, "{}", record.to_json());
+        }
+        // Full, drop the oldest lines and write the file again
+        lines.drain(..=lines.len() - self.max_entries);
+        lines.push(record.to_json());
+        std::fs::write(&self.path, lines.join("\n") + "\n")
+    }
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// FNV-1a, stable across builds and platforms
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    fnv1a_update(FNV_OFFSET_BASIS, bytes)
+}
+
+fn fnv1a_update(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Copies `from` to `to` like `std::fs::copy` and returns the [`fnv1a_hash`] of the contents,
+/// so the library is only read once
+#[cfg(feature = "hot_reload")]
+pub(crate) fn copy_and_hash(from: &Path, to: &Path) -> std::io::Result<u64> {
+    use std::{fs::File, io::Read};
+
+    let mut reader = File::open(from)?;
+    let mut writer = File::create(to)?;
+    let mut buf = vec![0; 64 * 1024];
+    let mut hash = FNV_OFFSET_BASIS;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hash = fnv1a_update(hash, &buf[..n]);
+        writer.write_all(&buf[..n])?;
+    }
+    writer.set_permissions(reader.metadata()?.permissions())?;
+    Ok(hash)
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&folder);
+        std::fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn append_keeps_the_newest_max_entries() {
+        let folder = temp_folder("hot_reload_history_rotation");
+        let history = HotReloadHistory::new(&folder, 3);
+        for version in 1..=5 {
+            let record = HotReloadRecord {
+                library_version: version,
+                ..Default::default()
+            };
+            history.append(&record).unwrap();
+        }
+        let versions = std::fs::read_to_string(history.path())
+            .unwrap()
+            .lines()
+            .map(|line| line.split(',').nth(1).unwrap().to_string())
+            .collect::<Vec<_>>();
+        let _ = std::fs::remove_dir_all(&folder);
+        assert_eq!(
+            versions,
+            [
+                "\"library_version\":3",
+                "\"library_version\":4",
+                "\"library_version\":5"
+            ]
+        );
+    }
+
+    #[test]
+    fn append_does_nothing_without_max_entries() {
+        let folder = temp_folder("hot_reload_history_disabled");
+        let history = HotReloadHistory::new(&folder, 0);
+        history.append(&HotReloadRecord::default()).unwrap();
+        let exists = history.path().exists();
+        let _ = std::fs::remove_dir_all(&folder);
+        assert!(!exists);
+    }
+
+    #[test]
+    fn json_string_escapes_control_characters() {
+        assert_eq!(
+            json_string("a \"b\" \\ c\nd\re\tf\u{1}g\u{1f}"),
+            r#""a \"b\" \\ c\nd\re\tf\u0001g\u001f""#
+        );
+        assert_eq!(json_string("é ✓"), "\"é ✓\"");
+    }
+}
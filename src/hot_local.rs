@@ -0,0 +1,76 @@
+use std::{any::TypeId, ptr::NonNull, sync::Arc};
+
+use libloading::Library;
+
+/// Drops a boxed value created by the hot library
+type DropFn = unsafe fn(NonNull<()>);
+
+/// Storage for a `Local<T>` param of a `#[make_hot(reset_locals)]` system.
+/// The host only keeps a pointer, the value is created by the hot library with
+/// `T::default()` so it always has the layout of the currently loaded build.
+/// The value is dropped after a reload and when the `HotLocal` is dropped, with drop code
+/// from the library that created it. That library is kept loaded until then.
+#[derive(Default)]
+pub struct HotLocal {
+    /// The value, its type and the function that drops it
+    value: Option<(NonNull<()>, TypeId, DropFn)>,
+    library_version: u64,
+    /// Library the value was created by, `None` for the statically linked version
+    library: Option<Arc<Library>>,
+}
+
+// The value is only created through `get_or_default`, which requires `T: Send`.
+// It is only accessed through `&mut HotLocal`.
+unsafe impl Send for HotLocal {}
+unsafe impl Sync for HotLocal {}
+
+impl HotLocal {
+    /// Drop the value if a different library has been loaded since it was created.
+    /// Called by the wrapper in the host.
+    pub fn reset_if_reloaded(&mut self, library_version: u64, library: Option<&Arc<Library>>) {
+        if self.library_version != library_version {
+            self.drop_value();
+            self.library_version = library_version;
+            self.library = library.cloned();
+        }
+    }
+
+    /// Called in the hot library. A value of another type is dropped and replaced
+    /// with `T::default()`.
+    pub fn get_or_default<T: Default + Send + 'static>(&mut self) -> &mut T {
+        if self
+            .value
+            .is_some_and(|(_, type_id, _)| type_id != TypeId::of::<T>())
+        {
+            self.drop_value();
+        }
+        let (value, _, _) = self.value.get_or_insert_with(|| {
+            (
+                NonNull::from(Box::leak(Box::<T>::default())).cast(),
+                TypeId::of::<T>(),
+                drop_boxed::<T>,
+            )
+        });
+        // Safety: created above as T, checked by the TypeId
+        unsafe { value.cast::<T>().as_mut() }
+    }
+
+    fn drop_value(&mut self) {
+        if let Some((value, _, drop)) = self.value.take() {
+            // Safety: `drop` was created together with `value`, and `self.library`
+            // keeps its code loaded
+            unsafe { drop(value) }
+        }
+    }
+}
+
+impl Drop for HotLocal {
+    fn drop(&mut self) {
+        // Runs before the fields are dropped, so the library is still loaded
+        self.drop_value();
+    }
+}
+
+unsafe fn drop_boxed<T>(value: NonNull<()>) {
+    drop(unsafe { Box::from_raw(value.cast::<T>().as_ptr()) });
+}
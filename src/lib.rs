@@ -1,12 +1,48 @@
 pub extern crate hot_reloading_macros;
+pub extern crate inventory;
 pub extern crate libloading;
 
+#[cfg(feature = "hot_reload")]
+mod bridge;
+mod callbacks;
+mod dyn_main;
+mod error;
+#[cfg(feature = "hot_reload")]
+mod globals;
+mod history;
+mod hot_local;
+mod registry;
+mod rerun;
+mod restart;
+#[cfg(feature = "snapshot")]
+mod snapshot;
+mod telemetry;
+#[cfg(feature = "hot_reload")]
+pub use bridge::*;
+pub use callbacks::*;
+pub use dyn_main::*;
+pub use error::*;
+#[cfg(feature = "hot_reload")]
+pub use globals::*;
+pub use history::*;
+pub use hot_local::*;
+pub use registry::*;
+pub use rerun::*;
+pub use restart::{request_restart, restart_count, take_restored_state, RestartRequest};
+#[cfg(feature = "snapshot")]
+pub use snapshot::*;
+pub use telemetry::*;
+
 use std::{
+    ffi::OsString,
     path::PathBuf,
-    time::{Duration, Instant},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use bevy::prelude::*;
+#[cfg(feature = "hot_reload")]
+use bevy::{app::MainScheduleOrder, diagnostic::Diagnostics};
+use bevy::{ecs::schedule::ScheduleLabel, prelude::*};
 use libloading::Library;
 #[cfg(feature = "hot_reload")]
 use std::any::TypeId;
@@ -17,6 +53,12 @@ pub struct HotReload {
     pub updated_this_frame: bool,
     pub last_update_time: Instant,
     pub disable_reload: bool,
+    /// Load the library on the next update even if it appears unchanged.
+    /// Also applies while `disable_reload` is set.
+    pub reload_requested: bool,
+    /// Where the time of the most recent reload went
+    pub timings: HotReloadTimings,
+    unload_guard: Arc<()>,
 }
 
 impl Default for HotReload {
@@ -24,41 +66,213 @@ impl Default for HotReload {
         HotReload {
             updated_this_frame: false,
             disable_reload: false,
+            reload_requested: false,
+            timings: HotReloadTimings::default(),
+            unload_guard: Arc::new(()),
             last_update_time: Instant::now().checked_sub(Duration::from_secs(1)).unwrap(),
         }
     }
 }
 
+impl HotReload {
+    /// Load the library again on the next update, even if the file appears unchanged.
+    pub fn request_reload(&mut self) {
+        self.reload_requested = true;
+    }
+
+    /// The library will not be unloaded while the returned guard, or any clone of it, is alive.
+    /// Hold one in async tasks or render world resources that may still point into the library.
+    pub fn unload_guard(&self) -> UnloadGuard {
+        UnloadGuard(self.unload_guard.clone())
+    }
+
+    /// True while any [`UnloadGuard`] is alive
+    pub fn unload_blocked(&self) -> bool {
+        Arc::strong_count(&self.unload_guard) > 1
+    }
+}
+
+/// Delays unloading the hot library until dropped. See [`HotReload::unload_guard`].
+#[derive(Clone)]
+pub struct UnloadGuard(#[allow(dead_code)] Arc<()>);
+
+/// Where in the frame the library is checked and reloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReloadPoint {
+    /// In [`HotReloadSchedule`], which runs before `First`, between frames
+    #[default]
+    BetweenFrames,
+    /// In `First`, in [`HotReloadSet`]. Systems in `First` that call hot functions
+    /// should be ordered `.after(HotReloadSet)`.
+    First,
+    /// In `PreUpdate`, in [`HotReloadSet`]. Systems in `PreUpdate` that call hot functions
+    /// should be ordered `.after(HotReloadSet)`.
+    PreUpdate,
+}
+
+/// The systems that check for, load and react to a new library, in the schedule
+/// picked with [`ReloadPoint`]
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HotReloadSet;
+
+/// Runs before `First` when using [`ReloadPoint::BetweenFrames`]
+#[derive(ScheduleLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HotReloadSchedule;
+
 #[derive(Debug, Event)]
 pub struct HotReloadEvent {
     pub last_update_time: Instant,
+    /// Hot functions that changed since the previous library, sorted by path.
+    /// Every `#[make_hot]` function in the running binary is compared.
+    pub changed: Vec<HotFnInfo>,
+}
+
+impl HotReloadEvent {
+    /// True if a hot function with this name or path changed in this reload
+    pub fn is_changed(&self, name: &str) -> bool {
+        self.changed.iter().any(|info| info.matches(name))
+    }
+}
+
+/// Send to control hot reloading at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Event)]
+pub enum HotReloadCommand {
+    /// Load the library again, even if it appears unchanged
+    Reload,
+    /// Run cargo build and reload once it finishes
+    Rebuild,
+    /// Stop checking for library updates
+    Pause,
+    /// Start checking for library updates again
+    Resume,
+    /// Exit and run the app again from a fresh build, for changes that can't be hot reloaded.
+    /// Requires the app to be started with [`DynMainBuilder`] or [`dyn_load_main`].
+    Restart,
 }
 
 /// Only for HotReload internal use. Must be pub because it is
 /// inserted as an arg on systems with #[make_hot]
 #[derive(Resource)]
 pub struct HotReloadLibInternalUseOnly {
-    pub library: Option<Library>,
+    pub library: Option<Arc<Library>>,
     pub updated_this_frame: bool,
     pub last_update_time: Instant,
     pub cargo_watch_child: Option<ChildGuard>,
     pub library_paths: LibPathSet,
+    /// Incremented for every load, used to name the `_hot_in_use` copy
+    pub library_version: u64,
+    /// Modified time of the compiler output when it was last copied and loaded
+    pub loaded_lib_modified: Option<SystemTime>,
+    pub cleanup: LibCleanupPolicy,
+    /// Arguments passed to cargo to build the library
+    pub build_args: Vec<OsString>,
+    /// Build started by `HotReloadCommand::Rebuild`
+    pub rebuild_child: Option<std::process::Child>,
+    pub reload_key: Option<KeyCode>,
+    pub build_watch: BuildWatch,
+    /// Build that finished but hasn't been loaded yet, logged with the next load
+    pub pending_build_time: Option<Duration>,
+    pub history: HotReloadHistory,
 }
 
+#[cfg(feature = "hot_reload")]
+impl HotReloadLibInternalUseOnly {
+    fn record_history(&self, record: HotReloadRecord) {
+        if let Err(e) = self.history.append(&record) {
+            warn!(
+                "Could not write to {}: {}",
+                self.history.path().to_string_lossy(),
+                e
+            );
+        }
+    }
+}
+
+impl Drop for HotReloadLibInternalUseOnly {
+    fn drop(&mut self) {
+        if self.cleanup.on_exit {
+            // Unload first so the file can also be removed on windows
+            self.library = None;
+            #[cfg(feature = "hot_reload")]
+            set_bridge_library(None);
+            self.library_paths.remove_hot_in_use_copies(0, None);
+        }
+    }
+}
+
+/// Which copies of the library are removed from the target dir.
+/// Only `_hot_in_use` copies are managed here, `_main_in_use` is removed by
+/// [`DynMainBuilder`] when the app returns, and at startup if a previous run was killed.
+#[derive(Clone, Debug)]
+pub struct LibCleanupPolicy {
+    /// Remove copies left over from previous runs when the plugin is built
+    pub on_startup: bool,
+    /// Remove copies on `AppExit` and when the plugin resources are dropped
+    pub on_exit: bool,
+    /// Maximum number of `_hot_in_use` copies kept in the target dir while running.
+    /// The currently loaded library is never removed.
+    pub max_library_files: usize,
+}
+
+impl Default for LibCleanupPolicy {
+    fn default() -> Self {
+        LibCleanupPolicy {
+            on_startup: true,
+            on_exit: true,
+            max_library_files: 2,
+        }
+    }
+}
+
+/// Configured with the builder methods or the public fields, e.g.
+/// `HotReloadPlugin::new().watch("crates/game").poll(true)`.
+///
+/// Some settings can be overridden with environment variables, so they can be changed
+/// without touching code:
+/// - `HOT_RELOAD_DISABLE=1` runs the code linked into the binary and never builds or loads the library
+/// - `HOT_RELOAD_POLL=1` or `0` sets [`HotReloadPlugin::poll`]
+/// - `HOT_RELOAD_LIB=name` sets [`HotReloadPlugin::library_name`]
+#[derive(Clone)]
 pub struct HotReloadPlugin {
     /// Start cargo watch with plugin
     pub auto_watch: bool,
     /// Should cargo watch use polling to detect file changes?
     /// Defaults to true when run in WSL, otherwise false.
     pub poll: bool,
+    /// Paths cargo watch watches for changes, `src` when empty
+    pub watch: Vec<PathBuf>,
+    /// Cargo profile the library is built with, e.g. `"hot"` for a `[profile.hot]`.
+    /// The library is then loaded from `target/<profile>` and built once at startup
+    /// if it's not there yet. Defaults to the profile of the running binary.
+    /// Only with [`HotReloadPlugin::bridge`], with bevy_dylib the library has to link the
+    /// same bevy_dylib build as the binary, so it needs the binary's profile.
+    pub profile: Option<String>,
+    /// Don't build or load the library, `#[make_hot]` functions run the code
+    /// linked into the binary as if the `hot_reload` feature was off
+    pub disabled: bool,
     /// Use bevy_dylib feature with cargo watch
     pub bevy_dylib: bool,
     /// The name of the library target in Cargo.toml:
     /// [lib]
     /// name = "lib_your_project_name"
     /// Defaults to your_project_name with lib_ prefix
-    /// This should be without .so or .dll
+    /// This should be without the `lib` file prefix cargo adds on unix, and without .so or .dll
     pub library_name: Option<String>,
+    /// When stale library copies are removed
+    pub cleanup: LibCleanupPolicy,
+    /// Key that sends `HotReloadCommand::Rebuild` when pressed
+    pub reload_key: Option<KeyCode>,
+    /// Where in the frame the library is reloaded
+    pub reload_point: ReloadPoint,
+    /// Run without bevy_dylib, with bevy statically linked into the host.
+    /// The hot library is built without `bevy/dynamic_linking` and TypeIds will differ,
+    /// so only `#[make_hot(bridge)]` functions that take plain data are hot.
+    /// `#[make_hot]` systems run the version linked into the host, as `HotSystemMode::Static`.
+    /// The app doesn't need to be in the library or started with [`dyn_load_main`].
+    pub bridge: bool,
+    /// Number of builds and loads kept in `hot_reload_history.jsonl` in the target dir.
+    /// 0 disables the history.
+    pub max_history_entries: usize,
 }
 
 impl Default for HotReloadPlugin {
@@ -66,77 +280,359 @@ impl Default for HotReloadPlugin {
         HotReloadPlugin {
             auto_watch: true,
             poll: is_wsl(),
+            watch: Vec::new(),
+            profile: None,
+            disabled: false,
             bevy_dylib: true,
             library_name: None,
+            cleanup: LibCleanupPolicy::default(),
+            reload_key: None,
+            reload_point: ReloadPoint::default(),
+            bridge: false,
+            max_history_entries: 1000,
         }
     }
 }
 
-impl Plugin for HotReloadPlugin {
-    #[cfg(not(feature = "hot_reload"))]
-    fn build(&self, app: &mut App) {
+impl HotReloadPlugin {
+    pub fn new() -> Self {
+        HotReloadPlugin::default()
+    }
+
+    /// Adds a path for cargo watch to watch, replacing the default `src`
+    pub fn watch(mut self, path: impl Into<PathBuf>) -> Self {
+        self.watch.push(path.into());
+        self
+    }
+
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    pub fn poll(mut self, poll: bool) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    pub fn auto_watch(mut self, auto_watch: bool) -> Self {
+        self.auto_watch = auto_watch;
+        self
+    }
+
+    pub fn bevy_dylib(mut self, bevy_dylib: bool) -> Self {
+        self.bevy_dylib = bevy_dylib;
+        self
+    }
+
+    pub fn library_name(mut self, library_name: impl Into<String>) -> Self {
+        self.library_name = Some(library_name.into());
+        self
+    }
+
+    pub fn cleanup(mut self, cleanup: LibCleanupPolicy) -> Self {
+        self.cleanup = cleanup;
+        self
+    }
+
+    pub fn reload_key(mut self, reload_key: KeyCode) -> Self {
+        self.reload_key = Some(reload_key);
+        self
+    }
+
+    pub fn reload_point(mut self, reload_point: ReloadPoint) -> Self {
+        self.reload_point = reload_point;
+        self
+    }
+
+    pub fn bridge(mut self, bridge: bool) -> Self {
+        self.bridge = bridge;
+        self
+    }
+
+    pub fn max_history_entries(mut self, max_history_entries: usize) -> Self {
+        self.max_history_entries = max_history_entries;
+        self
+    }
+
+    pub fn disabled(mut self, disabled: bool) -> Self {
+        self.disabled = disabled;
+        self
+    }
+
+    /// Applies `HOT_RELOAD_DISABLE`, `HOT_RELOAD_POLL` and `HOT_RELOAD_LIB`
+    pub fn with_env_overrides(mut self) -> Self {
+        if let Some(disabled) = env_flag("HOT_RELOAD_DISABLE") {
+            self.disabled = disabled;
+        }
+        if let Some(poll) = env_flag("HOT_RELOAD_POLL") {
+            self.poll = poll;
+        }
+        if let Some(library_name) = std::env::var("HOT_RELOAD_LIB")
+            .ok()
+            .filter(|name| !name.is_empty())
+        {
+            self.library_name = Some(library_name);
+        }
+        self
+    }
+
+    /// Setup without a library, the `#[make_hot]` functions run the code linked into the binary
+    fn build_disabled(&self, app: &mut App) {
         app.add_event::<HotReloadEvent>()
+            .add_event::<HotReloadCommand>()
+            .init_resource::<HotSystemRegistry>()
+            .init_resource::<HotReloadCallbacks>()
             .insert_resource(HotReload {
                 updated_this_frame: false,
                 disable_reload: true,
                 ..default()
             });
+        // The `#[make_hot]` wrappers still take this as a param, it never has a library
+        #[cfg(feature = "hot_reload")]
+        {
+            let library_paths = LibPathSet::new(self.library_name.clone()).unwrap();
+            app.insert_resource(HotReloadLibInternalUseOnly {
+                library: None,
+                updated_this_frame: false,
+                last_update_time: Instant::now(),
+                cargo_watch_child: None,
+                history: HotReloadHistory::new(&library_paths.folder, 0),
+                library_paths,
+                library_version: 0,
+                loaded_lib_modified: None,
+                cleanup: LibCleanupPolicy {
+                    on_exit: false,
+                    ..self.cleanup.clone()
+                },
+                build_args: Vec::new(),
+                rebuild_child: None,
+                reload_key: None,
+                build_watch: BuildWatch::default(),
+                pending_build_time: None,
+            });
+            app.world_mut()
+                .resource_mut::<HotSystemRegistry>()
+                .set_default_mode(HotSystemMode::Static);
+            set_hot_reload_disabled(true);
+        }
+    }
+}
+
+/// Joins args into one shell command line, quoting the ones that need it
+#[cfg(feature = "hot_reload")]
+fn quote_args(args: &[OsString]) -> String {
+    args.iter()
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=+@%,".contains(c))
+            {
+                return arg.into_owned();
+            }
+            if cfg!(windows) {
+                format!("\"{}\"", arg)
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `None` when the variable isn't set, false for `0`, `false`, `no`, `off` and empty
+fn env_flag(name: &str) -> Option<bool> {
+    let value = std::env::var(name).ok()?;
+    Some(!matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "" | "0" | "false" | "no" | "off"
+    ))
+}
+
+impl Plugin for HotReloadPlugin {
+    #[cfg(not(feature = "hot_reload"))]
+    fn build(&self, app: &mut App) {
+        self.build_disabled(app);
     }
 
     #[cfg(feature = "hot_reload")]
     fn build(&self, app: &mut App) {
+        let plugin = self.clone().with_env_overrides();
+        if plugin.disabled {
+            info!("Hot reloading is disabled");
+            return plugin.build_disabled(app);
+        }
+        plugin.build_hot(app);
+    }
+}
+
+impl HotReloadPlugin {
+    #[cfg(feature = "hot_reload")]
+    fn build_hot(&self, app: &mut App) {
         let mut child = None;
 
         let release_mode = false;
         #[cfg(not(debug_assertions))]
         let release_mode = true;
 
-        let library_paths = LibPathSet::new(self.library_name.clone()).unwrap();
+        let mut library_paths = LibPathSet::new(self.library_name.clone()).unwrap();
+        let target_dir = library_paths.folder.parent().unwrap().to_path_buf();
+        if let Some(profile) = &self.profile {
+            let folder = target_dir.join(match profile.as_str() {
+                "dev" | "test" => "debug",
+                "bench" => "release",
+                profile => profile,
+            });
+            if !self.bridge && folder != library_paths.folder {
+                panic!(
+                    "HotReloadPlugin::profile({:?}) needs `bridge`, without it the library has to be \
+                    built with the same profile as the binary to use the same bevy_dylib",
+                    profile
+                );
+            }
+            library_paths.folder = folder;
+        }
+
+        if self.cleanup.on_startup {
+            library_paths.remove_hot_in_use_copies(0, None);
+        }
+
+        // Kept as separate args, the target dir can contain spaces
+        let mut build_args = vec![
+            OsString::from("build"),
+            "--lib".into(),
+            "--target-dir".into(),
+            target_dir.clone().into(),
+        ];
+        match &self.profile {
+            Some(profile) => build_args.extend(["--profile".into(), profile.into()]),
+            None if release_mode => build_args.push("--release".into()),
+            None => (),
+        }
+        build_args.extend([
+            "--features".into(),
+            "ridiculous_bevy_hot_reloading/hot_reload".into(),
+        ]);
+        if self.bevy_dylib && !self.bridge {
+            build_args.extend(["--features".into(), "bevy/dynamic_linking".into()]);
+        }
 
+        // Only the binary's own profile is built by `cargo run`
+        if self.profile.is_some() && !library_paths.lib_file_path().exists() {
+            let build_cmd = quote_args(&build_args);
+            info!("Building the library with `cargo {}`", build_cmd);
+            let status = std::process::Command::new("cargo")
+                .args(&build_args)
+                .status();
+            if !status.is_ok_and(|status| status.success()) {
+                error!("Building the library with `cargo {}` failed", build_cmd);
+            }
+        }
+
+        let build_watch = BuildWatch::default();
+        let history = HotReloadHistory::new(&library_paths.folder, self.max_history_entries);
         if self.auto_watch {
-            let build_cmd = format!(
-                "build --lib --target-dir {} {} {} --features ridiculous_bevy_hot_reloading/hot_reload",
-                library_paths.folder.parent().unwrap().to_string_lossy(),
-                if release_mode { "--release" } else { "" },
-                if self.bevy_dylib {
-                    "--features bevy/dynamic_linking"
-                } else {
-                    ""
-                },
-            );
             child = Some(ChildGuard({
                 let mut command = std::process::Command::new("cargo");
                 command
                     .arg("watch")
                     .arg("--postpone")
-                    .arg("--watch-when-idle")
-                    .arg("-w")
-                    .arg("src")
-                    .arg("-x")
-                    .arg(build_cmd);
+                    .arg("--watch-when-idle");
+                if self.watch.is_empty() {
+                    command.arg("-w").arg("src");
+                }
+                for path in &self.watch {
+                    command.arg("-w").arg(path);
+                }
+                // cargo watch runs this through a shell
+                command.arg("-x").arg(quote_args(&build_args));
                 if self.poll {
                     command.arg("--poll");
                 }
-                command
+                // The output is piped to time the builds, so cargo can't tell it goes to a terminal
+                if std::io::IsTerminal::is_terminal(&std::io::stderr()) {
+                    let width = std::env::var("COLUMNS").unwrap_or_else(|_| "80".to_string());
+                    command
+                        .env("CARGO_TERM_COLOR", "always")
+                        .env("CARGO_TERM_PROGRESS_WHEN", "always")
+                        .env("CARGO_TERM_PROGRESS_WIDTH", width);
+                }
+                let mut child = command
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped())
                     .spawn()
-                    .expect("cargo watch command failed, make sure cargo watch is installed")
+                    .expect("cargo watch command failed, make sure cargo watch is installed");
+                // Output is forwarded unchanged, it is read to time the builds
+                if let Some(stdout) = child.stdout.take() {
+                    build_watch.forward_output(stdout, std::io::stdout());
+                }
+                if let Some(stderr) = child.stderr.take() {
+                    build_watch.forward_output(stderr, std::io::stderr());
+                }
+                child
             }));
         }
 
-        // TODO move as early as possible
-        app.add_systems(PreUpdate, (update_lib, check_type_ids).chain())
-            //.add_system_to_stage(CoreStage::PostUpdate, clean_up_watch)
-            .add_event::<HotReloadEvent>()
-            .insert_resource(HotReloadLibInternalUseOnly {
-                cargo_watch_child: child,
-                library: None,
-                updated_this_frame: false,
-                // Using 1 second ago so to trigger lib load immediately instead of in 1 second
-                last_update_time: Instant::now().checked_sub(Duration::from_secs(1)).unwrap(),
-                library_paths,
-            })
-            .insert_resource(HoldTypeId(TypeId::of::<HoldTypeId>()))
-            .insert_resource(HotReload::default());
+        let reload_schedule = match self.reload_point {
+            ReloadPoint::BetweenFrames => {
+                app.init_schedule(HotReloadSchedule);
+                app.world_mut()
+                    .resource_mut::<MainScheduleOrder>()
+                    .insert_before(First, HotReloadSchedule);
+                HotReloadSchedule.intern()
+            }
+            ReloadPoint::First => First.intern(),
+            ReloadPoint::PreUpdate => PreUpdate.intern(),
+        };
+
+        app.add_systems(
+            reload_schedule,
+            (
+                reload_key_input,
+                handle_commands,
+                registry::pin_libraries,
+                update_lib,
+                callbacks::run_reload_callbacks,
+                // TypeIds are expected to differ in bridge mode
+                check_type_ids.run_if(|bridge: Res<HotReloadBridge>| !bridge.0),
+                rerun::rerun_hot_systems,
+            )
+                .chain()
+                .in_set(HotReloadSet),
+        )
+        .add_systems(Last, (telemetry::collect_first_runs, clean_up_on_exit))
+        .add_event::<HotReloadEvent>()
+        .add_event::<HotReloadCommand>()
+        .insert_resource(HotReloadLibInternalUseOnly {
+            cargo_watch_child: child,
+            library: None,
+            updated_this_frame: false,
+            // Using 1 second ago so to trigger lib load immediately instead of in 1 second
+            last_update_time: Instant::now().checked_sub(Duration::from_secs(1)).unwrap(),
+            library_paths,
+            library_version: 0,
+            loaded_lib_modified: None,
+            cleanup: self.cleanup.clone(),
+            build_args,
+            rebuild_child: None,
+            reload_key: self.reload_key,
+            build_watch,
+            pending_build_time: None,
+            history,
+        })
+        .insert_resource(HoldTypeId(TypeId::of::<HoldTypeId>()))
+        .insert_resource(HotReloadBridge(self.bridge))
+        .init_resource::<HotSystemRegistry>()
+        .init_resource::<HotReloadCallbacks>()
+        .insert_resource(HotReload::default());
+        if self.bridge {
+            app.world_mut()
+                .resource_mut::<HotSystemRegistry>()
+                .set_bridge();
+        }
+        telemetry::register_diagnostics(app);
     }
 }
 
@@ -147,45 +643,88 @@ pub struct LibPathSet {
 }
 
 impl LibPathSet {
-    fn new(library_name: Option<String>) -> Option<Self> {
-        if let Ok(lib_path) = std::env::current_exe() {
-            let name = library_name.unwrap_or({
-                let stem = lib_path.file_stem().unwrap();
-                format!("lib_{}", stem.to_str().unwrap())
-            });
-            let folder = lib_path.parent().unwrap();
-
-            #[cfg(unix)]
-            let extension = String::from("so");
-            #[cfg(windows)]
-            let extension = String::from("dll");
+    fn new(library_name: Option<String>) -> std::io::Result<Self> {
+        let lib_path = std::env::current_exe()?;
+        let name = library_name.unwrap_or({
+            let stem = lib_path.file_stem().unwrap();
+            format!("lib_{}", stem.to_str().unwrap())
+        });
+        let folder = lib_path.parent().unwrap();
 
-            return Some(LibPathSet {
-                folder: (folder).to_path_buf(),
-                name,
-                extension,
-            });
-        }
-        None
+        // cargo names the file libname.so on linux, libname.dylib on macos and name.dll on windows
+        Ok(LibPathSet {
+            folder: (folder).to_path_buf(),
+            name: format!("{}{}", std::env::consts::DLL_PREFIX, name),
+            extension: std::env::consts::DLL_EXTENSION.to_string(),
+        })
     }
 
     /// File path the compiler outputs to
     fn lib_file_path(&self) -> PathBuf {
         self.folder.join(&self.name).with_extension(&self.extension)
     }
+
     #[cfg(feature = "hot_reload")]
-    /// File path copied to for hot reloads
-    fn hot_in_use_file_path(&self) -> PathBuf {
+    /// File path copied to for hot reloads, each load gets its own version
+    /// so the new library can be opened before the previous one is dropped
+    fn hot_in_use_file_path(&self, version: u64) -> PathBuf {
         self.folder
-            .join(format!("{}_hot_in_use", self.name))
+            .join(format!("{}_hot_in_use_{}", self.name, version))
             .with_extension(&self.extension)
     }
 
-    /// File path copied to for initial run
-    fn main_in_use_file_path(&self) -> PathBuf {
-        self.folder
-            .join(format!("{}_main_in_use", self.name))
-            .with_extension(&self.extension)
+    /// Removes `_hot_in_use` copies, oldest first, until at most `keep` are left.
+    /// `in_use` is never removed.
+    fn remove_hot_in_use_copies(&self, keep: usize, in_use: Option<&PathBuf>) {
+        let mut copies = self.copies(&format!("{}_hot_in_use", self.name));
+        copies.sort();
+        let excess = copies.len().saturating_sub(keep);
+        for (_, path) in copies.into_iter().take(excess) {
+            if Some(&path) != in_use {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Removes all `_main_in_use` copies
+    fn remove_main_in_use_copies(&self) {
+        for (_, path) in self.copies(&format!("{}_main_in_use", self.name)) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Libraries in the folder whose name starts with `prefix`, with their modified time
+    fn copies(&self, prefix: &str) -> Vec<(SystemTime, PathBuf)> {
+        let Ok(entries) = std::fs::read_dir(&self.folder) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == self.extension.as_str())
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with(prefix))
+            })
+            .filter_map(|path| Some((std::fs::metadata(&path).ok()?.modified().ok()?, path)))
+            .collect()
+    }
+
+    /// File path copied to for the initial run, restarts get their own copy
+    fn main_in_use_file_path(&self, restart: u32) -> PathBuf {
+        let name = if restart == 0 {
+            format!("{}_main_in_use", self.name)
+        } else {
+            format!("{}_main_in_use_{}", self.name, restart)
+        };
+        self.folder.join(name).with_extension(&self.extension)
+    }
+
+    /// File the state of a restart request is written to for the next process
+    fn restart_state_file_path(&self) -> PathBuf {
+        self.folder.join(format!("{}_restart_state", self.name))
     }
 }
 
@@ -194,55 +733,228 @@ fn update_lib(
     mut hot_reload_int: ResMut<HotReloadLibInternalUseOnly>,
     mut hot_reload: ResMut<HotReload>,
     mut event: EventWriter<HotReloadEvent>,
+    mut diagnostics: Diagnostics,
 ) {
     hot_reload_int.updated_this_frame = false;
     hot_reload.updated_this_frame = false;
-    if hot_reload.disable_reload {
+    if let Some(build) = hot_reload_int.build_watch.take_finished() {
+        hot_reload.timings.build = Some(build.duration);
+        record_duration(&mut diagnostics, &HOT_RELOAD_BUILD_TIME, build.duration);
+        if build.success {
+            hot_reload_int.pending_build_time = Some(build.duration);
+        } else {
+            hot_reload_int.record_history(HotReloadRecord {
+                build_time: Some(build.duration),
+                success: false,
+                error: Some("build failed".to_string()),
+                ..HotReloadRecord::now()
+            });
+        }
+    }
+    if hot_reload.disable_reload && !hot_reload.reload_requested {
         return;
     }
 
     let lib_file_path = hot_reload_int.library_paths.lib_file_path();
-    let hot_in_use_file_path = hot_reload_int.library_paths.hot_in_use_file_path();
 
     // copy over and load lib if it has been updated, or hasn't been initially
-    if lib_file_path.is_file() {
-        if hot_in_use_file_path.is_file() {
-            let hot_lib_meta = std::fs::metadata(&hot_in_use_file_path).unwrap();
-            let main_lib_meta = std::fs::metadata(&lib_file_path).unwrap();
-            if hot_lib_meta.modified().unwrap() < main_lib_meta.modified().unwrap()
-                && hot_reload_int.last_update_time.elapsed() > Duration::from_secs(1)
-            {
-                hot_reload_int.library = None;
-                let _ = std::fs::copy(lib_file_path, &hot_in_use_file_path);
-            }
-        } else {
-            hot_reload_int.library = None;
-            std::fs::copy(lib_file_path, &hot_in_use_file_path).unwrap();
+    if let Ok(main_lib_meta) = std::fs::metadata(&lib_file_path) {
+        let lib_modified = main_lib_meta.modified().ok();
+        let needs_load = hot_reload_int.library.is_none()
+            || hot_reload.reload_requested
+            || (lib_modified > hot_reload_int.loaded_lib_modified
+                && hot_reload_int.last_update_time.elapsed() > Duration::from_secs(1));
+        // Guards only hold on to a loaded library, the first load can't be blocked
+        let blocked = hot_reload_int.library.is_some() && hot_reload.unload_blocked();
+        if !needs_load || blocked {
+            hot_reload.updated_this_frame = hot_reload_int.updated_this_frame;
+            hot_reload.last_update_time = hot_reload_int.last_update_time;
+            return;
         }
-        if hot_reload_int.library.is_none() {
-            unsafe {
-                let lib = libloading::Library::new(&hot_in_use_file_path).unwrap_or_else(|_| {
-                    panic!(
-                        "Can't open required library {}",
-                        &hot_in_use_file_path.to_string_lossy()
-                    )
-                });
-                // TODO set globals like IoTaskPool here
 
-                hot_reload_int.library = Some(lib);
-                hot_reload_int.updated_this_frame = true;
-                hot_reload_int.last_update_time = Instant::now();
-                event.write(HotReloadEvent {
-                    last_update_time: hot_reload_int.last_update_time,
-                });
+        let version = hot_reload_int.library_version + 1;
+        let hot_in_use_file_path = hot_reload_int.library_paths.hot_in_use_file_path(version);
+        let copy_start = Instant::now();
+        let library_hash = match copy_and_hash(&lib_file_path, &hot_in_use_file_path) {
+            Ok(hash) => Some(hash),
+            Err(e) => {
+                // The compiler may still be writing the library, try again next frame
+                warn!(
+                    "Could not copy {} to {}: {}",
+                    lib_file_path.to_string_lossy(),
+                    hot_in_use_file_path.to_string_lossy(),
+                    e
+                );
+                return;
             }
+        };
+        let copy_time = copy_start.elapsed();
+        let build_time = hot_reload_int.pending_build_time.take();
+        unsafe {
+            let load_start = Instant::now();
+            let lib = match libloading::Library::new(&hot_in_use_file_path) {
+                Ok(lib) => lib,
+                Err(source) => {
+                    let error = HotReloadError::Load {
+                        path: hot_in_use_file_path.clone(),
+                        source,
+                    };
+                    hot_reload_int.record_history(HotReloadRecord {
+                        library_version: version,
+                        library_hash,
+                        build_time,
+                        success: false,
+                        error: Some(error.to_string()),
+                        ..HotReloadRecord::now()
+                    });
+                    // Nothing can run without the initial library
+                    if hot_reload_int.library.is_none() {
+                        panic!("{}", error);
+                    }
+                    // Keep running the previous library until the next build
+                    error!("{}", error);
+                    hot_reload.reload_requested = false;
+                    hot_reload_int.loaded_lib_modified = lib_modified;
+                    return;
+                }
+            };
+            let load_time = load_start.elapsed();
+            let created_task_pools = match lib.get::<HotInit>(HOT_INIT_SYMBOL.as_bytes()) {
+                Ok(init) => init(&HotGlobals::from_host()),
+                Err(_) => {
+                    warn!("{} not found, globals were not set", HOT_INIT_SYMBOL);
+                    false
+                }
+            };
+
+            let changed = match &hot_reload_int.library {
+                Some(previous) => changed_hot_fns(previous, &lib),
+                None => Vec::new(),
+            };
+
+            // The previous library is dropped here, after the new one is open
+            let lib = Arc::new(lib);
+            if created_task_pools {
+                // Its task pool threads run its code until the process exits
+                std::mem::forget(lib.clone());
+            }
+            set_bridge_library(Some(lib.clone()));
+            hot_reload_int.library = Some(lib);
+            hot_reload_int.library_version = version;
+            hot_reload.reload_requested = false;
+            hot_reload.timings.copy = Some(copy_time);
+            hot_reload.timings.load = Some(load_time);
+            hot_reload.timings.first_run.clear();
+            record_duration(&mut diagnostics, &HOT_RELOAD_COPY_TIME, copy_time);
+            record_duration(&mut diagnostics, &HOT_RELOAD_LOAD_TIME, load_time);
+            hot_reload_int.loaded_lib_modified = lib_modified;
+            hot_reload_int.updated_this_frame = true;
+            hot_reload_int.last_update_time = Instant::now();
+            hot_reload_int.record_history(HotReloadRecord {
+                library_version: version,
+                library_hash,
+                changed: changed.iter().map(|info| info.name.to_string()).collect(),
+                build_time,
+                success: true,
+                ..HotReloadRecord::now()
+            });
+            event.write(HotReloadEvent {
+                last_update_time: hot_reload_int.last_update_time,
+                changed,
+            });
         }
+
+        let max_library_files = hot_reload_int.cleanup.max_library_files;
+        hot_reload_int
+            .library_paths
+            .remove_hot_in_use_copies(max_library_files, Some(&hot_in_use_file_path));
     }
 
     hot_reload.updated_this_frame = hot_reload_int.updated_this_frame;
     hot_reload.last_update_time = hot_reload_int.last_update_time;
 }
 
+#[cfg(feature = "hot_reload")]
+fn reload_key_input(
+    keys: Option<Res<ButtonInput<KeyCode>>>,
+    hot_reload_int: Res<HotReloadLibInternalUseOnly>,
+    mut commands: EventWriter<HotReloadCommand>,
+) {
+    if let (Some(keys), Some(key)) = (keys, hot_reload_int.reload_key) {
+        if keys.just_pressed(key) {
+            commands.write(HotReloadCommand::Rebuild);
+        }
+    }
+}
+
+#[cfg(feature = "hot_reload")]
+fn handle_commands(
+    mut commands: EventReader<HotReloadCommand>,
+    mut hot_reload_int: ResMut<HotReloadLibInternalUseOnly>,
+    mut hot_reload: ResMut<HotReload>,
+    mut app_exit: EventWriter<AppExit>,
+) {
+    for command in commands.read() {
+        match command {
+            HotReloadCommand::Reload => hot_reload.request_reload(),
+            HotReloadCommand::Restart => {
+                request_restart(RestartRequest {
+                    build_args: Some(hot_reload_int.build_args.clone()),
+                    state: None,
+                });
+                app_exit.write(AppExit::Success);
+            }
+            HotReloadCommand::Pause => hot_reload.disable_reload = true,
+            HotReloadCommand::Resume => hot_reload.disable_reload = false,
+            HotReloadCommand::Rebuild => {
+                if hot_reload_int.rebuild_child.is_some() {
+                    continue;
+                }
+                let child = std::process::Command::new("cargo")
+                    .args(&hot_reload_int.build_args)
+                    .spawn();
+                match child {
+                    Ok(child) => {
+                        hot_reload_int.build_watch.start();
+                        hot_reload_int.rebuild_child = Some(child);
+                    }
+                    Err(e) => error!("Could not start cargo build: {}", e),
+                }
+            }
+        }
+    }
+
+    let finished = match &mut hot_reload_int.rebuild_child {
+        Some(child) => child.try_wait().unwrap_or(None),
+        None => None,
+    };
+    if let Some(status) = finished {
+        hot_reload_int.rebuild_child = None;
+        hot_reload_int.build_watch.finish(status.success());
+        if status.success() {
+            hot_reload.request_reload();
+        } else {
+            error!("cargo build failed: {}", status);
+        }
+    }
+}
+
+#[cfg(feature = "hot_reload")]
+fn clean_up_on_exit(
+    mut app_exit: EventReader<AppExit>,
+    hot_reload_int: Res<HotReloadLibInternalUseOnly>,
+) {
+    if app_exit.read().last().is_some() && hot_reload_int.cleanup.on_exit {
+        // The loaded library is removed when HotReloadLibInternalUseOnly is dropped
+        let in_use = hot_reload_int
+            .library_paths
+            .hot_in_use_file_path(hot_reload_int.library_version);
+        hot_reload_int
+            .library_paths
+            .remove_hot_in_use_copies(0, Some(&in_use));
+    }
+}
+
 pub struct ChildGuard(pub std::process::Child);
 
 impl Drop for ChildGuard {
@@ -258,6 +970,10 @@ impl Drop for ChildGuard {
 #[cfg(feature = "hot_reload")]
 struct HoldTypeId(TypeId);
 
+#[derive(Resource)]
+#[cfg(feature = "hot_reload")]
+struct HotReloadBridge(bool);
+
 #[cfg(feature = "hot_reload")]
 mod ridiculous_bevy_hot_reloading {
     pub use super::*;
@@ -282,23 +998,14 @@ fn check_type_ids(type_id: Res<HoldTypeId>, _hot_reload_int: Res<HotReloadLibInt
 
 /// Copies library file before running so the original can be overwritten
 /// Only needed if using bevy_dylib
+/// Panics if the library can't be loaded, use [`DynMainBuilder`] to handle errors or pass args.
 pub fn dyn_load_main(main_function_name: &str, library_name: Option<String>) {
-    if let Some(lib_paths) = LibPathSet::new(library_name) {
-        let lib_file_path = lib_paths.lib_file_path();
-        let main_in_use_file_path = lib_paths.main_in_use_file_path();
-
-        if lib_file_path.is_file() {
-            std::fs::copy(lib_file_path, &main_in_use_file_path).unwrap();
-            unsafe {
-                if let Ok(lib) = libloading::Library::new(main_in_use_file_path) {
-                    let func: libloading::Symbol<unsafe extern "C" fn()> =
-                        lib.get(main_function_name.as_bytes()).unwrap();
-                    func();
-                }
-            }
-        } else {
-            panic!("Could not find library file {:?}", lib_file_path);
-        }
+    let mut builder = DynMainBuilder::new(main_function_name);
+    if let Some(library_name) = library_name {
+        builder = builder.library_name(library_name);
+    }
+    if let Err(e) = builder.run() {
+        panic!("{}", e);
     }
 }
 
@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use libloading::Library;
+
+use crate::HotReloadLibInternalUseOnly;
+
+/// A `#[make_hot]` function linked into the running binary.
+/// Functions with the same name in different modules only differ in `path` and `symbol`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HotFnInfo {
+    /// Function name, e.g. `"rotate"`
+    pub name: &'static str,
+    /// Module path and name, e.g. `"my_game::shapes::rotate"`
+    pub path: &'static str,
+    /// Name of the exported function in the library
+    pub symbol: &'static str,
+}
+
+impl HotFnInfo {
+    /// True if `name` is the name or the path of this function
+    pub fn matches(&self, name: &str) -> bool {
+        self.name == name || self.path == name
+    }
+}
+
+// Every `#[make_hot]` function submits its info, so all of them are known before
+// the first reload, not only the ones that have been called
+inventory::collect!(HotFnInfo);
+
+pub(crate) fn hot_fns() -> Vec<HotFnInfo> {
+    let mut hot_fns = inventory::iter::<HotFnInfo>
+        .into_iter()
+        .copied()
+        .collect::<Vec<_>>();
+    hot_fns.sort_by_key(|info| (info.path, info.symbol));
+    hot_fns.dedup();
+    hot_fns
+}
+
+/// Hot functions whose body hash differs between the two libraries,
+/// or that are missing from either because their signature changed
+#[cfg(feature = "hot_reload")]
+pub(crate) fn changed_hot_fns(previous: &Library, current: &Library) -> Vec<HotFnInfo> {
+    let body_hash = |library: &Library, info: &HotFnInfo| unsafe {
+        library
+            .get::<extern "C" fn() -> u64>(format!("{}_body_hash", info.symbol).as_bytes())
+            .ok()
+            .map(|body_hash| body_hash())
+    };
+    hot_fns()
+        .into_iter()
+        .filter(|info| {
+            let previous = body_hash(previous, info);
+            previous.is_none() || previous != body_hash(current, info)
+        })
+        .collect()
+}
+
+/// Which version of a `#[make_hot]` system is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HotSystemMode {
+    /// Run from the most recently loaded library
+    #[default]
+    Hot,
+    /// Keep running from the library that was loaded when it was pinned
+    Pinned,
+    /// Run the version statically linked into the running binary
+    Static,
+}
+
+/// Per function control over which version of a `#[make_hot]` system runs.
+/// Modes are set by function name, e.g. `"rotate"`, which applies to every hot system
+/// with that name, or by path, e.g. `"my_game::shapes::rotate"`, which takes precedence.
+#[derive(Resource, Default)]
+pub struct HotSystemRegistry {
+    /// By function name or path
+    modes: HashMap<String, HotSystemMode>,
+    /// Mode of functions without one set, `Static` when hot reloading is disabled
+    /// and in bridge mode
+    default_mode: HotSystemMode,
+    /// In bridge mode the library has its own bevy, so systems always run `Static`
+    bridge: bool,
+    pinned: HashMap<HotFnInfo, (Arc<Library>, u64)>,
+    /// Library version each system last ran from
+    seen: Mutex<HashMap<HotFnInfo, u64>>,
+    first_runs: Mutex<Vec<(HotFnInfo, Duration)>>,
+}
+
+/// Where the `#[make_hot]` wrapper should call the function from
+pub enum HotSystemTarget<'a> {
+    Static,
+    Library {
+        library: &'a Arc<Library>,
+        version: u64,
+        /// First time the function runs from this library
+        first_run: bool,
+    },
+    NotLoaded,
+}
+
+impl HotSystemTarget<'_> {
+    /// The library version the function will run from, 0 for static
+    pub fn version(&self) -> u64 {
+        match self {
+            HotSystemTarget::Library { version, .. } => *version,
+            HotSystemTarget::Static | HotSystemTarget::NotLoaded => 0,
+        }
+    }
+
+    /// The library the function will run from, `None` for static
+    pub fn library(&self) -> Option<&Arc<Library>> {
+        match self {
+            HotSystemTarget::Library { library, .. } => Some(library),
+            HotSystemTarget::Static | HotSystemTarget::NotLoaded => None,
+        }
+    }
+}
+
+impl HotSystemRegistry {
+    /// `name` is a function name or path, see [`HotSystemRegistry`]
+    pub fn set_mode(&mut self, name: impl Into<String>, mode: HotSystemMode) {
+        let name = name.into();
+        self.modes.insert(name, mode);
+        let modes = &self.modes;
+        let default_mode = self.default_mode;
+        self.pinned
+            .retain(|info, _| mode_of(modes, default_mode, info) == HotSystemMode::Pinned);
+    }
+
+    /// The mode set for this function name or path
+    pub fn mode(&self, name: &str) -> HotSystemMode {
+        self.modes.get(name).copied().unwrap_or(self.default_mode)
+    }
+
+    /// The mode that applies to this function, by path and then by name
+    pub fn mode_of(&self, info: &HotFnInfo) -> HotSystemMode {
+        mode_of(&self.modes, self.default_mode, info)
+    }
+
+    /// Mode of every function that hasn't been given one with [`Self::set_mode`]
+    pub fn set_default_mode(&mut self, mode: HotSystemMode) {
+        self.default_mode = mode;
+    }
+
+    /// Systems can't be called in a bridge library, only `#[make_hot(bridge)]` functions
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn set_bridge(&mut self) {
+        self.bridge = true;
+        self.default_mode = HotSystemMode::Static;
+    }
+
+    /// Hot systems that have run
+    pub fn systems(&self) -> Vec<(HotFnInfo, HotSystemMode)> {
+        let mut systems = self
+            .seen
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|info| (*info, self.mode_of(info)))
+            .collect::<Vec<_>>();
+        systems.sort_by_key(|(info, _)| info.path);
+        systems
+    }
+
+    /// Used by the `#[make_hot]` wrapper
+    pub fn target<'a>(
+        &'a self,
+        info: HotFnInfo,
+        hot_reload_int: &'a HotReloadLibInternalUseOnly,
+    ) -> HotSystemTarget<'a> {
+        let current = hot_reload_int
+            .library
+            .as_ref()
+            .map(|library| (library, hot_reload_int.library_version));
+        let mode = match self.bridge {
+            true => HotSystemMode::Static,
+            false => self.mode_of(&info),
+        };
+        let library = match mode {
+            HotSystemMode::Static => {
+                self.seen.lock().unwrap().entry(info).or_insert(0);
+                return HotSystemTarget::Static;
+            }
+            HotSystemMode::Pinned => self
+                .pinned
+                .get(&info)
+                .map(|(library, version)| (library, *version))
+                .or(current),
+            HotSystemMode::Hot => current,
+        };
+        match library {
+            Some((library, version)) => {
+                let last_version = self.seen.lock().unwrap().insert(info, version);
+                HotSystemTarget::Library {
+                    library,
+                    version,
+                    first_run: last_version != Some(version),
+                }
+            }
+            None => {
+                self.seen.lock().unwrap().entry(info).or_insert(0);
+                HotSystemTarget::NotLoaded
+            }
+        }
+    }
+
+    /// Used by the `#[make_hot]` wrapper when [`HotSystemTarget::Library`] has `first_run` set
+    pub fn record_first_run(&self, info: HotFnInfo, duration: Duration) {
+        self.first_runs.lock().unwrap().push((info, duration));
+    }
+
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn take_first_runs(&self) -> Vec<(HotFnInfo, Duration)> {
+        std::mem::take(&mut *self.first_runs.lock().unwrap())
+    }
+}
+
+fn mode_of(
+    modes: &HashMap<String, HotSystemMode>,
+    default_mode: HotSystemMode,
+    info: &HotFnInfo,
+) -> HotSystemMode {
+    modes
+        .get(info.path)
+        .or_else(|| modes.get(info.name))
+        .copied()
+        .unwrap_or(default_mode)
+}
+
+/// Holds on to the current library for systems that were pinned since the last reload
+#[cfg(feature = "hot_reload")]
+pub(crate) fn pin_libraries(
+    mut registry: ResMut<HotSystemRegistry>,
+    hot_reload_int: Res<HotReloadLibInternalUseOnly>,
+) {
+    let Some(library) = &hot_reload_int.library else {
+        return;
+    };
+    let registry = &mut *registry;
+    for info in registry.seen.get_mut().unwrap().keys() {
+        if mode_of(&registry.modes, registry.default_mode, info) == HotSystemMode::Pinned
+            && !registry.pinned.contains_key(info)
+        {
+            registry
+                .pinned
+                .insert(*info, (library.clone(), hot_reload_int.library_version));
+        }
+    }
+}
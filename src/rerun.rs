@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+
+#[cfg(feature = "hot_reload")]
+use bevy::ecs::event::EventCursor;
+use bevy::{ecs::system::SystemIdMarker, prelude::*};
+
+use crate::{HotFnInfo, HotReloadLibInternalUseOnly, HotSystemMode, HotSystemRegistry};
+
+/// Added by `#[make_hot(rerun_on_reload)]` to every entity the system spawned,
+/// they are despawned before the system runs again
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HotSpawned(pub HotFnInfo);
+
+/// The exclusive system generated by `#[make_hot(rerun_on_reload)]`
+type RerunSystem = fn(&mut World);
+
+/// Systems marked `#[make_hot(rerun_on_reload)]` that have been scheduled
+#[derive(Resource, Default)]
+pub struct HotRerunSystems {
+    systems: Vec<RerunEntry>,
+}
+
+#[derive(Clone, Copy)]
+struct RerunEntry {
+    info: HotFnInfo,
+    /// Run again by `rerun_hot_systems`, which only exists with `hot_reload`
+    #[cfg_attr(not(feature = "hot_reload"), allow(dead_code))]
+    system: RerunSystem,
+    /// False until the hot system has actually run, startup skips it before the first load
+    ran: bool,
+}
+
+impl HotRerunSystems {
+    fn entry(&mut self, info: HotFnInfo, system: RerunSystem) -> &mut RerunEntry {
+        match self.systems.iter().position(|entry| entry.info == info) {
+            Some(index) => &mut self.systems[index],
+            None => {
+                self.systems.push(RerunEntry {
+                    info,
+                    system,
+                    ran: false,
+                });
+                self.systems.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Used by the `#[make_hot(rerun_on_reload)]` wrapper. `system` is the wrapper itself,
+/// `run` runs the hot system, an error is logged.
+pub fn run_rerun_on_reload<O, E: std::fmt::Display>(
+    world: &mut World,
+    info: HotFnInfo,
+    system: RerunSystem,
+    run: impl FnOnce(&mut World) -> Result<O, E>,
+) {
+    world
+        .get_resource_or_init::<HotRerunSystems>()
+        .entry(info, system);
+
+    // Startup runs before the library is first loaded,
+    // rerun_hot_systems runs it once the library is loaded
+    let is_static = world
+        .get_resource::<HotSystemRegistry>()
+        .is_some_and(|registry| registry.mode_of(&info) == HotSystemMode::Static);
+    let is_loaded = world
+        .get_resource::<HotReloadLibInternalUseOnly>()
+        .is_some_and(|hot_reload_int| hot_reload_int.library.is_some());
+    if !is_static && !is_loaded {
+        return;
+    }
+
+    let existing = world
+        .iter_entities()
+        .map(|entity| entity.id())
+        .collect::<HashSet<_>>();
+    if let Err(e) = run(world) {
+        error!("Could not run {} on reload: {}", info.name, e);
+    }
+    world
+        .resource_mut::<HotRerunSystems>()
+        .entry(info, system)
+        .ran = true;
+    // The first run also spawns the entity that holds the cached system, leave that alone
+    let spawned = world
+        .iter_entities()
+        .filter(|entity| !entity.contains::<SystemIdMarker>())
+        .map(|entity| entity.id())
+        .filter(|entity| !existing.contains(entity))
+        .collect::<Vec<_>>();
+    for entity in spawned {
+        world.entity_mut(entity).insert(HotSpawned(info));
+    }
+}
+
+/// Despawns what each `rerun_on_reload` system spawned and runs it again after a load
+/// that changed it. Static and pinned systems keep running the same code, so they only
+/// get the run they skipped before the first load.
+#[cfg(feature = "hot_reload")]
+pub(crate) fn rerun_hot_systems(
+    world: &mut World,
+    mut cursor: Local<EventCursor<crate::HotReloadEvent>>,
+) {
+    let changed = cursor
+        .read(world.resource::<Events<crate::HotReloadEvent>>())
+        .flat_map(|event| event.changed.iter().map(|info| info.symbol))
+        .collect::<Vec<_>>();
+    if !world
+        .resource::<HotReloadLibInternalUseOnly>()
+        .updated_this_frame
+    {
+        return;
+    }
+    let Some(systems) = world
+        .get_resource::<HotRerunSystems>()
+        .map(|rerun| rerun.systems.clone())
+    else {
+        return;
+    };
+    for entry in systems {
+        let mode = world
+            .get_resource::<HotSystemRegistry>()
+            .map_or(HotSystemMode::Hot, |registry| registry.mode_of(&entry.info));
+        let rerun = match mode {
+            HotSystemMode::Hot => !entry.ran || changed.contains(&entry.info.symbol),
+            HotSystemMode::Pinned | HotSystemMode::Static => !entry.ran,
+        };
+        if !rerun {
+            continue;
+        }
+        let spawned = world
+            .query::<(Entity, &HotSpawned)>()
+            .iter(world)
+            .filter(|(_, spawned)| spawned.0 == entry.info)
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+        for entity in spawned {
+            // Children are despawned with their parent and may already be gone
+            if let Ok(entity) = world.get_entity_mut(entity) {
+                entity.despawn();
+            }
+        }
+        (entry.system)(world);
+    }
+}
@@ -0,0 +1,90 @@
+//! Cold restarts for changes that can't be hot reloaded.
+//!
+//! This runs inside the library started by [`crate::DynMainBuilder`]. The host reads the
+//! request through the exported functions after the app returns, then runs the executable
+//! again in a new process. Calling main again in the same process would set up process wide
+//! state like the winit event loop a second time.
+
+use std::{
+    ffi::OsString,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+/// Handed from the library to the host when the app exits for a restart
+#[derive(Debug, Clone, Default)]
+pub struct RestartRequest {
+    /// Cargo args the host runs before loading the library again, so it gets a fresh build
+    pub build_args: Option<Vec<OsString>>,
+    /// Passed to the next run, see [`take_restored_state`]
+    pub state: Option<Vec<u8>>,
+}
+
+static RESTART_REQUESTED: AtomicBool = AtomicBool::new(false);
+static RESTART_REQUEST: Mutex<Option<RestartRequest>> = Mutex::new(None);
+static RESTORED_STATE: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+pub(crate) const RESTART_REQUESTED_SYMBOL: &str = "ridiculous_bevy_hot_restart_requested";
+pub(crate) const TAKE_RESTART_REQUEST_SYMBOL: &str = "ridiculous_bevy_hot_take_restart_request";
+pub(crate) const SET_RESTORED_STATE_SYMBOL: &str = "ridiculous_bevy_hot_set_restored_state";
+
+/// Set on the restarted process to the number of restarts so far
+pub(crate) const RESTART_COUNT_ENV: &str = "RIDICULOUS_BEVY_HOT_RESTART";
+/// Set on the restarted process to the file holding [`RestartRequest::state`]
+pub(crate) const RESTART_STATE_ENV: &str = "RIDICULOUS_BEVY_HOT_RESTART_STATE";
+
+/// Ask the host to run the app again from a fresh library once it exits.
+/// Only has an effect when started with [`crate::DynMainBuilder`] or [`crate::dyn_load_main`].
+/// The app still needs to exit, `HotReloadCommand::Restart` does both.
+pub fn request_restart(request: RestartRequest) {
+    *RESTART_REQUEST.lock().unwrap() = Some(request);
+    RESTART_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// True if the pending restart request already has a state
+#[cfg(feature = "snapshot")]
+pub(crate) fn restart_state_is_set() -> bool {
+    RESTART_REQUEST
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|request| request.state.is_some())
+}
+
+/// Sets the state of a pending restart request, unless it was set by the app
+#[cfg(feature = "snapshot")]
+pub(crate) fn set_restart_state(state: Vec<u8>) {
+    if let Some(request) = RESTART_REQUEST.lock().unwrap().as_mut() {
+        request.state.get_or_insert(state);
+    }
+}
+
+/// How many times the app has been restarted, 0 on the first run
+pub fn restart_count() -> u32 {
+    std::env::var(RESTART_COUNT_ENV)
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0)
+}
+
+/// State passed in [`RestartRequest::state`] by the previous run, if this is a restart
+pub fn take_restored_state() -> Option<Vec<u8>> {
+    RESTORED_STATE.lock().unwrap().take()
+}
+
+#[no_mangle]
+pub fn ridiculous_bevy_hot_restart_requested() -> bool {
+    RESTART_REQUESTED.load(Ordering::SeqCst)
+}
+
+#[no_mangle]
+pub fn ridiculous_bevy_hot_take_restart_request() -> Option<RestartRequest> {
+    RESTART_REQUEST.lock().unwrap().take()
+}
+
+#[no_mangle]
+pub fn ridiculous_bevy_hot_set_restored_state(state: Option<Vec<u8>>) {
+    *RESTORED_STATE.lock().unwrap() = state;
+}
@@ -0,0 +1,193 @@
+//! Keeps the World across cold restarts, so they feel more like a hot reload.
+//!
+//! Before the app exits for a restart, reflectable entities and resources are saved as a
+//! [`DynamicScene`] and handed to the next run, which writes them back in `PreStartup`.
+//! If the app already set [`crate::RestartRequest::state`] the World is not saved.
+
+use std::any::TypeId;
+
+use bevy::{
+    ecs::entity::EntityHashMap,
+    prelude::*,
+    reflect::{serde::TypedReflectSerializer, PartialReflect, TypeRegistry},
+    scene::{ron, serde::SceneDeserializer, DynamicSceneBuilder, SceneFilter},
+    window::{Monitor, PrimaryWindow, Window},
+};
+use serde::de::DeserializeSeed;
+
+use crate::restart::{
+    restart_state_is_set, ridiculous_bevy_hot_restart_requested, set_restart_state,
+};
+
+/// Saves the World when a restart is requested and restores it in the next run.
+/// Components and resources that can't be serialized, like asset handles, are skipped.
+/// Add `.run_if(not(restored_from_snapshot))` to startup systems that spawn entities
+/// which are restored, so they are not spawned twice.
+pub struct HotSnapshotPlugin {
+    /// Entities with any of these components are not saved
+    pub skip_entities_with: Vec<TypeId>,
+}
+
+impl Default for HotSnapshotPlugin {
+    fn default() -> Self {
+        HotSnapshotPlugin {
+            skip_entities_with: vec![
+                TypeId::of::<Window>(),
+                TypeId::of::<PrimaryWindow>(),
+                TypeId::of::<Monitor>(),
+            ],
+        }
+    }
+}
+
+impl Plugin for HotSnapshotPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SnapshotSkipList(self.skip_entities_with.clone()))
+            .add_systems(PreStartup, restore_snapshot)
+            .add_systems(Last, save_snapshot);
+    }
+}
+
+/// Inserted when the World was restored from a snapshot taken before a restart
+#[derive(Resource)]
+pub struct HotSnapshotRestored;
+
+/// Run condition that is true when the World was restored from a snapshot
+pub fn restored_from_snapshot(restored: Option<Res<HotSnapshotRestored>>) -> bool {
+    restored.is_some()
+}
+
+#[derive(Resource)]
+struct SnapshotSkipList(Vec<TypeId>);
+
+fn save_snapshot(world: &mut World, mut saved: Local<bool>) {
+    if *saved || !ridiculous_bevy_hot_restart_requested() {
+        return;
+    }
+    *saved = true;
+    if restart_state_is_set() {
+        info!("RestartRequest::state is set by the app, the World is not saved");
+        return;
+    }
+    match snapshot(world) {
+        Ok(snapshot) => set_restart_state(snapshot.into_bytes()),
+        Err(e) => error!("Could not save World before restart: {}", e),
+    }
+}
+
+fn snapshot(world: &World) -> Result<String, ron::Error> {
+    let skip_list = &world.resource::<SnapshotSkipList>().0;
+    let type_registry = world.resource::<AppTypeRegistry>().read();
+
+    let mut entities = Vec::new();
+    let mut unserializable = Vec::new();
+    // Names of the components and resources that are not saved
+    let mut skipped = Vec::new();
+    for entity in world.iter_entities() {
+        let components = entity
+            .archetype()
+            .components()
+            .filter_map(|id| {
+                let info = world.components().get_info(id)?;
+                Some((info.type_id()?, info.name()))
+            })
+            .collect::<Vec<_>>();
+        if components
+            .iter()
+            .any(|(type_id, _)| skip_list.contains(type_id))
+        {
+            continue;
+        }
+        let mut serializable = false;
+        for (type_id, name) in components {
+            let Some(reflect) = type_registry
+                .get_type_data::<ReflectComponent>(type_id)
+                .and_then(|reflect| reflect.reflect(entity))
+            else {
+                continue;
+            };
+            if unserializable.contains(&type_id) {
+                continue;
+            }
+            if can_serialize(reflect.as_partial_reflect(), &type_registry) {
+                serializable = true;
+            } else {
+                unserializable.push(type_id);
+                skipped.push(name);
+            }
+        }
+        if serializable {
+            entities.push(entity.id());
+        }
+    }
+
+    let mut resource_filter = SceneFilter::allow_all();
+    for (component_id, _) in world.storages().resources.iter() {
+        let Some(info) = world.components().get_info(component_id) else {
+            continue;
+        };
+        let Some(type_id) = info.type_id() else {
+            continue;
+        };
+        let Some(reflect) = type_registry
+            .get_type_data::<ReflectResource>(type_id)
+            .and_then(|reflect| reflect.reflect(world).ok())
+        else {
+            continue;
+        };
+        if !can_serialize(reflect.as_partial_reflect(), &type_registry) {
+            resource_filter = resource_filter.deny_by_id(type_id);
+            skipped.push(info.name());
+        }
+    }
+    if !skipped.is_empty() {
+        info!(
+            "Not saved for the restart, can't be serialized: {}",
+            skipped.join(", ")
+        );
+    }
+
+    let component_filter = unserializable
+        .into_iter()
+        .fold(SceneFilter::allow_all(), SceneFilter::deny_by_id);
+
+    DynamicSceneBuilder::from_world(world)
+        .with_component_filter(component_filter)
+        .with_resource_filter(resource_filter)
+        .extract_entities(entities.into_iter())
+        .extract_resources()
+        .build()
+        .serialize(&type_registry)
+}
+
+fn can_serialize(value: &dyn PartialReflect, type_registry: &TypeRegistry) -> bool {
+    ron::to_string(&TypedReflectSerializer::new(value, type_registry)).is_ok()
+}
+
+fn restore_snapshot(world: &mut World) {
+    let Some(snapshot) = crate::take_restored_state() else {
+        return;
+    };
+    let scene = {
+        let type_registry = world.resource::<AppTypeRegistry>().read();
+        let Ok(snapshot) = std::str::from_utf8(&snapshot) else {
+            error!("Could not restore World after restart: snapshot is not utf8");
+            return;
+        };
+        let deserializer = SceneDeserializer {
+            type_registry: &type_registry,
+        };
+        ron::Deserializer::from_str(snapshot)
+            .map_err(ron::Error::from)
+            .and_then(|mut ron| deserializer.deserialize(&mut ron))
+    };
+    match scene {
+        Ok(scene) => match scene.write_to_world(world, &mut EntityHashMap::default()) {
+            Ok(()) => {
+                world.insert_resource(HotSnapshotRestored);
+            }
+            Err(e) => error!("Could not restore World after restart: {}", e),
+        },
+        Err(e) => error!("Could not restore World after restart: {}", e),
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use bevy::diagnostic::DiagnosticPath;
+#[cfg(feature = "hot_reload")]
+use bevy::{diagnostic::Diagnostics, prelude::*};
+#[cfg(feature = "hot_reload")]
+use std::io::{Read, Write};
+
+#[cfg(feature = "hot_reload")]
+use crate::{HotReload, HotSystemRegistry};
+
+/// Time spent in cargo building the library, as seen by the watcher
+pub const HOT_RELOAD_BUILD_TIME: DiagnosticPath =
+    DiagnosticPath::const_new("hot_reload/build_time");
+/// Time spent copying the library to its `_hot_in_use` path
+pub const HOT_RELOAD_COPY_TIME: DiagnosticPath = DiagnosticPath::const_new("hot_reload/copy_time");
+/// Time spent in `Library::new`
+pub const HOT_RELOAD_LOAD_TIME: DiagnosticPath = DiagnosticPath::const_new("hot_reload/load_time");
+/// Time each hot system took the first time it ran from a newly loaded library
+pub const HOT_RELOAD_FIRST_RUN_TIME: DiagnosticPath =
+    DiagnosticPath::const_new("hot_reload/first_run_time");
+
+/// Where the time of the most recent reload went. Found in [`HotReload::timings`].
+///
+/// [`HotReload::timings`]: crate::HotReload::timings
+#[derive(Debug, Clone, Default)]
+pub struct HotReloadTimings {
+    /// Duration of the last build that finished
+    pub build: Option<Duration>,
+    pub copy: Option<Duration>,
+    pub load: Option<Duration>,
+    /// First call of each hot system after the last load, by function path
+    pub first_run: HashMap<String, Duration>,
+}
+
+/// A build that has finished since it was last taken
+#[derive(Debug, Clone, Copy)]
+pub struct FinishedBuild {
+    pub duration: Duration,
+    pub success: bool,
+}
+
+#[derive(Default)]
+struct BuildWatchState {
+    started: Option<Instant>,
+    finished: Option<FinishedBuild>,
+}
+
+/// Times builds run by cargo watch and `HotReloadCommand::Rebuild`
+#[derive(Clone, Default)]
+pub struct BuildWatch(Arc<Mutex<BuildWatchState>>);
+
+impl BuildWatch {
+    pub fn start(&self) {
+        self.0.lock().unwrap().started = Some(Instant::now());
+    }
+
+    pub fn finish(&self, success: bool) {
+        let mut state = self.0.lock().unwrap();
+        if let Some(started) = state.started.take() {
+            state.finished = Some(FinishedBuild {
+                duration: started.elapsed(),
+                success,
+            });
+        }
+    }
+
+    /// The last build that finished, if it wasn't taken already
+    pub fn take_finished(&self) -> Option<FinishedBuild> {
+        self.0.lock().unwrap().finished.take()
+    }
+
+    /// Forwards the output of cargo watch unchanged and looks for the lines it prints
+    /// around each command, e.g. `[Running 'cargo build ...']` and
+    /// `[Finished running. Exit status: 0]`
+    #[cfg(feature = "hot_reload")]
+    pub(crate) fn forward_output(
+        &self,
+        mut output: impl Read + Send + 'static,
+        mut forward_to: impl Write + Send + 'static,
+    ) {
+        let build_watch = self.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 4096];
+            let mut line = Vec::new();
+            loop {
+                let n = match output.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                // Written as it comes, progress bars are redrawn with `\r` and no newline
+                let _ = forward_to.write_all(&buf[..n]);
+                let _ = forward_to.flush();
+                for &byte in &buf[..n] {
+                    if byte == b'\n' || byte == b'\r' {
+                        build_watch.check_line(&strip_ansi(&line));
+                        line.clear();
+                    } else {
+                        line.push(byte);
+                    }
+                }
+            }
+        });
+    }
+
+    #[cfg(feature = "hot_reload")]
+    fn check_line(&self, line: &str) {
+        if line.starts_with("[Running") {
+            self.start();
+        } else if let Some(status) = line.strip_prefix("[Finished running. Exit status: ") {
+            self.finish(status.trim_end_matches(']') == "0");
+        }
+    }
+}
+
+/// Removes color codes, so colored output can be matched
+#[cfg(feature = "hot_reload")]
+fn strip_ansi(line: &[u8]) -> String {
+    let line = String::from_utf8_lossy(line);
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // `ESC [ params letter`
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped.trim().to_string()
+}
+
+#[cfg(feature = "hot_reload")]
+pub(crate) fn register_diagnostics(app: &mut App) {
+    use bevy::diagnostic::{Diagnostic, RegisterDiagnostic};
+
+    for path in [
+        HOT_RELOAD_BUILD_TIME,
+        HOT_RELOAD_COPY_TIME,
+        HOT_RELOAD_LOAD_TIME,
+        HOT_RELOAD_FIRST_RUN_TIME,
+    ] {
+        app.register_diagnostic(Diagnostic::new(path).with_suffix("ms"));
+    }
+}
+
+#[cfg(feature = "hot_reload")]
+pub(crate) fn record_duration(
+    diagnostics: &mut Diagnostics,
+    path: &DiagnosticPath,
+    duration: Duration,
+) {
+    diagnostics.add_measurement(path, || duration.as_secs_f64() * 1000.0);
+}
+
+/// Moves first run times recorded by the `#[make_hot]` wrappers into [`HotReloadTimings`]
+#[cfg(feature = "hot_reload")]
+pub(crate) fn collect_first_runs(
+    registry: Res<HotSystemRegistry>,
+    mut hot_reload: ResMut<HotReload>,
+    mut diagnostics: Diagnostics,
+) {
+    for (info, duration) in registry.take_first_runs() {
+        record_duration(&mut diagnostics, &HOT_RELOAD_FIRST_RUN_TIME, duration);
+        hot_reload
+            .timings
+            .first_run
+            .insert(info.path.to_string(), duration);
+    }
+}
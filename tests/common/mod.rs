@@ -0,0 +1,59 @@
+//! Helpers for the tests that build fixture crates depending on this crate.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Shared by all fixtures, so bevy is only built once
+pub fn fixture_target_dir() -> PathBuf {
+    // target/debug/deps/test-hash
+    let exe = std::env::current_exe().unwrap();
+    exe.ancestors().nth(3).unwrap().join("hot_reload_fixture")
+}
+
+/// Creates a crate in `dir` with this manifest, depending on this crate and `dependencies`
+pub fn create_fixture_crate(dir: &Path, manifest: &str, dependencies: &str) {
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            r#"{}
+[dependencies]
+ridiculous_bevy_hot_reloading = {{ path = {:?}, features = ["hot_reload"] }}
+{}
+[workspace]
+"#,
+            manifest, manifest_dir, dependencies
+        ),
+    )
+    .unwrap();
+    // Same dependency versions as the test, so they are only built once
+    let _ = std::fs::copy(
+        Path::new(manifest_dir).join("Cargo.lock"),
+        dir.join("Cargo.lock"),
+    );
+}
+
+/// `cargo <subcommand>` for the fixture crate in `dir`, in the shared target dir
+pub fn fixture_cargo(dir: &Path, subcommand: &str) -> Command {
+    let mut command = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    command
+        .arg(subcommand)
+        .arg("--manifest-path")
+        .arg(dir.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(fixture_target_dir());
+    command
+}
+
+/// File name of the library `name` as cargo writes it, e.g. `libname.so`
+pub fn library_file_name(name: &str) -> String {
+    format!(
+        "{}{}.{}",
+        std::env::consts::DLL_PREFIX,
+        name,
+        std::env::consts::DLL_EXTENSION
+    )
+}
@@ -0,0 +1,51 @@
+//! `HOT_RELOAD_DISABLE` turns the plugin off in a `hot_reload` build, `#[make_hot]`
+//! functions then run the code linked into the binary and no library is needed.
+//! This is its own test binary as the setting is global.
+
+use bevy::prelude::*;
+use ridiculous_bevy_hot_reloading::{
+    hot_reload_disabled, hot_reloading_macros::make_hot, HotReload, HotReloadPlugin, HotSystemMode,
+    HotSystemRegistry,
+};
+
+#[derive(Resource, Default)]
+struct Frames(u32);
+
+#[make_hot]
+fn count_frames(mut frames: ResMut<Frames>) {
+    frames.0 += 1;
+}
+
+#[make_hot(bridge)]
+fn double(value: u32) -> u32 {
+    value * 2
+}
+
+#[test]
+fn env_disables_hot_reload() {
+    std::env::set_var("HOT_RELOAD_DISABLE", "1");
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(
+            HotReloadPlugin::new()
+                .auto_watch(false)
+                .library_name("missing_library")
+                .max_history_entries(0),
+        )
+        .init_resource::<Frames>()
+        .add_systems(Update, count_frames);
+    app.update();
+    app.update();
+
+    assert!(hot_reload_disabled());
+    assert!(app.world().resource::<HotReload>().disable_reload);
+    assert_eq!(
+        app.world()
+            .resource::<HotSystemRegistry>()
+            .mode("count_frames"),
+        HotSystemMode::Static
+    );
+    assert_eq!(app.world().resource::<Frames>().0, 2);
+    assert_eq!(double(4), 8);
+}
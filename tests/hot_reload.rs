@@ -0,0 +1,346 @@
+//! Builds fixture libraries with `#[make_hot]` functions, loads them in a headless app,
+//! rebuilds them with different code and checks that the app runs the new code.
+//!
+//! The bridge fixture links bevy statically, so it uses `HotReloadPlugin { bridge: true }`
+//! and `#[make_hot(bridge)]` functions that only take plain data. The system fixture uses
+//! bevy_dylib and `#[make_hot]` systems, and runs as its own app started with
+//! `dyn_load_main`. The first run builds bevy for the fixtures in `target/hot_reload_fixture`.
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::Stdio,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use bevy::prelude::*;
+use common::{create_fixture_crate, fixture_cargo, fixture_target_dir, library_file_name};
+use ridiculous_bevy_hot_reloading::{
+    hot_reloading_macros::make_hot, HotReloadEvent, HotReloadPlugin,
+};
+
+// Same names and signatures as in the fixture, so the same symbols are looked up
+#[make_hot(bridge, name = "fixture_value")]
+pub fn fixture_value(base: u32) -> u32 {
+    base
+}
+
+#[make_hot(bridge, name = "fixture_size", instances(u16, u64))]
+pub fn fixture_size<T>() -> usize {
+    std::mem::size_of::<T>()
+}
+
+#[make_hot(bridge, name = "fixture_tasks")]
+pub fn fixture_tasks(count: u32) -> u32 {
+    bevy::tasks::ComputeTaskPool::get()
+        .scope(|scope| {
+            for _ in 0..count {
+                scope.spawn(async { 1 });
+            }
+        })
+        .into_iter()
+        .sum()
+}
+
+#[derive(Resource, Default)]
+struct Observed {
+    value: u32,
+    reloads: Vec<Vec<&'static str>>,
+}
+
+fn observe(mut observed: ResMut<Observed>, mut events: EventReader<HotReloadEvent>) {
+    observed.value = fixture_value(10);
+    for event in events.read() {
+        let changed = event.changed.iter().map(|info| info.name).collect();
+        observed.reloads.push(changed);
+    }
+}
+
+/// A copy of the fixture crate in the temp dir, so its source can be rewritten
+struct Fixture {
+    dir: PathBuf,
+    /// Where `HotReloadPlugin` looks for the library, next to the test executable
+    lib_name: String,
+    exe_dir: PathBuf,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let exe = std::env::current_exe().unwrap();
+        let exe_dir = exe.parent().unwrap().to_path_buf();
+        let lib_name = format!("hot_reload_fixture_{}", std::process::id());
+        let dir = std::env::temp_dir().join(&lib_name);
+        create_fixture_crate(
+            &dir,
+            r#"[package]
+name = "hot_reload_fixture"
+version = "0.0.0"
+edition = "2021"
+
+[lib]
+crate-type = ["dylib"]
+"#,
+            r#"bevy = { version = "0.16", default-features = false }"#,
+        );
+
+        Fixture {
+            dir,
+            lib_name,
+            exe_dir,
+        }
+    }
+
+    /// Builds the fixture with `fixture_value` adding `add`, and copies it next to the test
+    fn build(&self, add: u32) {
+        std::fs::write(
+            self.dir.join("src/lib.rs"),
+            format!(
+                r#"use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge, name = "fixture_value")]
+pub fn fixture_value(base: u32) -> u32 {{
+    base + {add}
+}}
+
+#[make_hot(bridge, name = "fixture_size", instances(u16, u64))]
+pub fn fixture_size<T>() -> usize {{
+    std::mem::size_of::<T>() * {add}
+}}
+
+// Uses the task pool the library creates for itself
+#[make_hot(bridge, name = "fixture_tasks")]
+pub fn fixture_tasks(count: u32) -> u32 {{
+    bevy::tasks::ComputeTaskPool::get()
+        .scope(|scope| {{
+            for _ in 0..count {{
+                scope.spawn(async {{ 1 }});
+            }}
+        }})
+        .into_iter()
+        .sum()
+}}
+"#,
+                add = add
+            ),
+        )
+        .unwrap();
+
+        let status = fixture_cargo(&self.dir, "build")
+            .arg("--lib")
+            .status()
+            .unwrap();
+        assert!(status.success(), "fixture build failed");
+
+        std::fs::copy(
+            fixture_target_dir()
+                .join("debug")
+                .join(library_file_name("hot_reload_fixture")),
+            self.exe_dir.join(library_file_name(&self.lib_name)),
+        )
+        .unwrap();
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+        let _ = std::fs::remove_file(self.exe_dir.join(library_file_name(&self.lib_name)));
+    }
+}
+
+#[test]
+fn reload_swaps_behavior() {
+    let fixture = Fixture::new();
+    fixture.build(1);
+
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugins(HotReloadPlugin {
+            auto_watch: false,
+            bridge: true,
+            library_name: Some(fixture.lib_name.clone()),
+            max_history_entries: 0,
+            ..default()
+        })
+        .init_resource::<Observed>()
+        .add_systems(Update, observe);
+
+    app.update();
+    let observed = app.world().resource::<Observed>();
+    assert_eq!(observed.value, 11);
+    assert_eq!(observed.reloads, vec![Vec::<&str>::new()]);
+    assert_eq!((fixture_size::<u16>(), fixture_size::<u64>()), (2, 8));
+    assert_eq!(fixture_tasks(4), 4);
+
+    fixture.build(2);
+
+    // Changes are only picked up a second after the last load
+    let start = Instant::now();
+    while app.world().resource::<Observed>().reloads.len() < 2 {
+        assert!(
+            start.elapsed() < Duration::from_secs(30),
+            "library was not reloaded"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+        app.update();
+    }
+    let observed = app.world().resource::<Observed>();
+    assert_eq!(observed.value, 12);
+    assert_eq!(observed.reloads[1], vec!["fixture_size", "fixture_value"]);
+    assert_eq!((fixture_size::<u16>(), fixture_size::<u64>()), (4, 16));
+}
+
+/// A crate with `#[make_hot]` systems and a binary that runs them with `dyn_load_main`,
+/// built with bevy_dylib like an app using this crate
+struct SystemFixture {
+    dir: PathBuf,
+}
+
+const SYSTEM_FIXTURE_LIB: &str = r#"use std::time::Duration;
+
+use bevy::{app::ScheduleRunnerPlugin, prelude::*};
+use ridiculous_bevy_hot_reloading::{hot_reloading_macros::make_hot, HotReloadPlugin};
+
+#[derive(Resource, Default)]
+pub struct Value(pub u32);
+
+#[make_hot]
+pub fn set_value(mut value: ResMut<Value>) {
+    value.0 = VALUE;
+}
+
+// The type of `last` changes between the builds
+#[make_hot(reset_locals)]
+pub fn report(value: Res<Value>, mut last: Local<LAST_TYPE>, mut exit: EventWriter<AppExit>) {
+    let current: LAST_TYPE = LAST_VALUE;
+    if *last != current {
+        *last = current;
+        println!("value {}", value.0);
+    }
+    if value.0 == 2 {
+        exit.write(AppExit::Success);
+    }
+}
+
+// Stops the app if the test is gone
+fn give_up(time: Res<Time<Real>>, mut exit: EventWriter<AppExit>) {
+    if time.elapsed() > Duration::from_secs(180) {
+        exit.write(AppExit::error());
+    }
+}
+
+#[no_mangle]
+pub fn fixture_main() {
+    let exit = App::new()
+        .add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_millis(10))))
+        .add_plugins(
+            HotReloadPlugin::new()
+                .auto_watch(false)
+                .library_name("hot_reload_system_fixture")
+                .max_history_entries(0),
+        )
+        .init_resource::<Value>()
+        .add_systems(Update, ((set_value, report).chain(), give_up))
+        .run();
+    if exit.is_error() {
+        std::process::exit(1);
+    }
+}
+"#;
+
+impl SystemFixture {
+    fn new() -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("hot_reload_system_fixture_{}", std::process::id()));
+        create_fixture_crate(
+            &dir,
+            r#"[package]
+name = "hot_reload_system_fixture"
+version = "0.0.0"
+edition = "2021"
+
+[lib]
+crate-type = ["dylib"]
+
+[[bin]]
+name = "hot_reload_system_fixture_main"
+path = "src/main.rs"
+"#,
+            r#"bevy = { version = "0.16", default-features = false, features = ["dynamic_linking"] }"#,
+        );
+        std::fs::write(
+            dir.join("src/main.rs"),
+            r#"fn main() {
+    ridiculous_bevy_hot_reloading::dyn_load_main(
+        "fixture_main",
+        Some("hot_reload_system_fixture".to_string()),
+    );
+}
+"#,
+        )
+        .unwrap();
+        SystemFixture { dir }
+    }
+
+    /// Writes the library with `set_value` setting `value` and builds it.
+    /// `report` keeps the last value as a number in the first build and as a string after.
+    fn build_lib(&self, value: u32) {
+        let (last_type, last_value) = match value {
+            1 => ("u32", "value.0"),
+            _ => ("String", "value.0.to_string()"),
+        };
+        std::fs::write(
+            self.dir.join("src/lib.rs"),
+            SYSTEM_FIXTURE_LIB
+                .replace("LAST_TYPE", last_type)
+                .replace("LAST_VALUE", last_value)
+                .replace("VALUE", &value.to_string()),
+        )
+        .unwrap();
+        let status = fixture_cargo(&self.dir, "build").status().unwrap();
+        assert!(status.success(), "fixture build failed");
+    }
+}
+
+impl Drop for SystemFixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn systems_reload_with_bevy_dylib() {
+    let fixture = SystemFixture::new();
+    fixture.build_lib(1);
+
+    let mut child = fixture_cargo(&fixture.dir, "run")
+        .arg("--quiet")
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (sender, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let _ = sender.send(line);
+        }
+    });
+    let wait_for = |expected: &str| loop {
+        let line = lines
+            .recv_timeout(Duration::from_secs(120))
+            .unwrap_or_else(|_| panic!("fixture did not print {:?}", expected));
+        if line == expected {
+            break;
+        }
+    };
+
+    wait_for("value 1");
+    // The running app loads the new build and runs the new set_value,
+    // and report with a different type in its Local
+    fixture.build_lib(2);
+    wait_for("value 2");
+    assert!(child.wait().unwrap().success(), "fixture app failed");
+}
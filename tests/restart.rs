@@ -0,0 +1,94 @@
+//! Builds a fixture library whose main requests a restart on the first run and checks that
+//! the restarted process gets the state back.
+//!
+//! The restart runs this test executable again with the same args, so this is the only
+//! test in this file. The restarted test builds its own fixture and runs it with
+//! `restart_count() == 1`. On unix it replaces the first process, elsewhere its exit code
+//! is returned by the first run.
+
+mod common;
+
+use common::{create_fixture_crate, fixture_cargo, fixture_target_dir, library_file_name};
+use ridiculous_bevy_hot_reloading::{restart_count, DynMainArgs, DynMainBuilder};
+
+/// Builds the fixture and copies it next to the test executable, returns its library name
+fn build_fixture() -> String {
+    let exe = std::env::current_exe().unwrap();
+    let exe_dir = exe.parent().unwrap();
+    let lib_name = format!("restart_fixture_{}", std::process::id());
+    let dir = std::env::temp_dir().join(&lib_name);
+    create_fixture_crate(
+        &dir,
+        r#"[package]
+name = "restart_fixture"
+version = "0.0.0"
+edition = "2021"
+
+[lib]
+crate-type = ["dylib"]
+"#,
+        "",
+    );
+    std::fs::write(
+        dir.join("src/lib.rs"),
+        r#"use ridiculous_bevy_hot_reloading::{
+    request_restart, restart_count, take_restored_state, DynMainArgs, RestartRequest,
+};
+
+#[no_mangle]
+pub fn restart_fixture_main(_args: &DynMainArgs) -> i32 {
+    match (restart_count(), take_restored_state()) {
+        (0, _) => {
+            request_restart(RestartRequest {
+                build_args: None,
+                state: Some(b"restored".to_vec()),
+            });
+            // Replaced by the exit code of the restarted process
+            7
+        }
+        (1, Some(state)) if state == b"restored" => 0,
+        _ => 1,
+    }
+}
+"#,
+    )
+    .unwrap();
+
+    let status = fixture_cargo(&dir, "build").arg("--lib").status().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert!(status.success(), "fixture build failed");
+
+    std::fs::copy(
+        fixture_target_dir()
+            .join("debug")
+            .join(library_file_name("restart_fixture")),
+        exe_dir.join(library_file_name(&lib_name)),
+    )
+    .unwrap();
+    lib_name
+}
+
+#[test]
+fn restart_runs_a_new_process() {
+    let lib_name = build_fixture();
+    let exe = std::env::current_exe().unwrap();
+    // Left behind by a run that was killed, removed when the first run starts
+    let stale_copy = exe
+        .parent()
+        .unwrap()
+        .join(library_file_name(&format!("{}_main_in_use_9", lib_name)));
+    if restart_count() == 0 {
+        std::fs::write(&stale_copy, b"").unwrap();
+    }
+    let result = DynMainBuilder::with_args("restart_fixture_main", DynMainArgs::default())
+        .library_name(lib_name.clone())
+        .run();
+    let _ = std::fs::remove_file(exe.parent().unwrap().join(library_file_name(&lib_name)));
+
+    // 0 from the restarted fixture, and 0 from the restarted test
+    assert_eq!(result.unwrap(), 0, "restart {} failed", restart_count());
+    assert!(
+        !stale_copy.exists(),
+        "stale _main_in_use copy was not removed"
+    );
+}
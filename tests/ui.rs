@@ -0,0 +1,9 @@
+//! Checks that `#[make_hot]` expands to code that compiles, and that unsupported
+//! inputs fail with a clear error. Update the `.stderr` files with `TRYBUILD=overwrite`.
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass/*.rs");
+    t.compile_fail("tests/ui/fail/*.rs");
+}
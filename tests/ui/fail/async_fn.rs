@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge)]
+async fn load() -> u32 {
+    1
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge)]
+fn speed(time: f32) -> f32 {
+    let scale: u32 = "fast";
+    time * scale as f32
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge)]
+pub fn label(name: String, scale: f32) -> f32 {
+    name.len() as f32 * scale
+}
+
+fn main() {}
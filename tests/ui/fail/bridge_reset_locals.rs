@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge, reset_locals)]
+fn speed() -> f32 {
+    1.0
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge)]
+fn speed<T: Into<f32>>(value: T) -> f32 {
+    value.into() * 2.0
+}
+
+fn main() {}
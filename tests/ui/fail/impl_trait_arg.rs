@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge)]
+fn total(values: &[impl Into<f32>]) -> f32 {
+    values.len() as f32
+}
+
+fn main() {}
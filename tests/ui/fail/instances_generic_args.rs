@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge, instances(Vec<u8>))]
+fn size<T>() -> usize {
+    std::mem::size_of::<T>()
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge, instances(u32, u64))]
+fn size<A, B>() -> usize {
+    std::mem::size_of::<A>() + std::mem::size_of::<B>()
+}
+
+fn main() {}
@@ -0,0 +1,8 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[make_hot(bridge)]
+fn add((a, b): (f32, f32)) -> f32 {
+    a + b
+}
+
+fn main() {}
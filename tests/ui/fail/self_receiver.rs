@@ -0,0 +1,14 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+struct Spinner;
+
+impl Spinner {
+    #[make_hot(bridge)]
+    fn speed(&self) -> f32 {
+        1.0
+    }
+}
+
+fn main() {
+    let _ = Spinner;
+}
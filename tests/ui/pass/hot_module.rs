@@ -0,0 +1,31 @@
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::hot_module;
+
+#[hot_module]
+pub mod gameplay {
+    use bevy::prelude::*;
+
+    #[derive(Component)]
+    pub struct Shape;
+
+    pub fn rotate(mut query: Query<&mut Transform, With<Shape>>, time: Res<Time>) {
+        for mut transform in &mut query {
+            transform.rotate_y(time.delta_secs());
+        }
+    }
+
+    #[not_hot]
+    pub fn count_frames(mut frames: Local<u32>) {
+        *frames += 1;
+    }
+
+    pub fn speed(time: f32) -> f32 {
+        time * 2.0
+    }
+}
+
+fn main() {
+    use bevy::prelude::*;
+
+    App::new().add_systems(Update, (gameplay::rotate, gameplay::count_frames));
+    let _ = gameplay::speed(1.0);
+}
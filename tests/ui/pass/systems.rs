@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use ridiculous_bevy_hot_reloading::hot_reloading_macros::make_hot;
+
+#[derive(Component)]
+struct Shape;
+
+#[derive(Component, Default)]
+struct Goblin;
+
+#[derive(Resource, Default)]
+struct LastFrame(u32);
+
+mod enemies {
+    #[derive(bevy::prelude::Component, Default)]
+    pub struct Orc;
+}
+
+#[make_hot]
+pub fn rotate(mut query: Query<&mut Transform, With<Shape>>, time: Res<Time>) {
+    for mut transform in &mut query {
+        transform.rotate_y(time.delta_secs());
+    }
+}
+
+#[make_hot]
+fn spawn(mut commands: Commands, time: Res<Time>) {
+    commands.spawn((Shape, Transform::from_xyz(time.elapsed_secs(), 0.0, 0.0)));
+}
+
+#[make_hot]
+fn should_rotate(time: Res<Time>) -> bool
+where
+    Time: Send,
+{
+    time.elapsed_secs() > 1.0
+}
+
+#[make_hot(reset_locals)]
+fn count(mut counter: Local<u32>, _time: Res<Time>) {
+    *counter += 1;
+}
+
+// The hot function gets `&mut Local<u32>`
+#[make_hot]
+fn frames(mut frames: Local<u32>, mut last_frame: ResMut<LastFrame>) {
+    **frames += 1;
+    last_frame.0 = **frames;
+}
+
+#[make_hot(rerun_on_reload)]
+pub fn setup(mut commands: Commands) {
+    commands.spawn(Shape);
+}
+
+#[make_hot(instances(Goblin, enemies::Orc))]
+fn spawn_enemy<T: Component + Default>(mut commands: Commands) {
+    commands.spawn(T::default());
+}
+
+#[make_hot(bridge, name = "pass::bridge")]
+pub fn spin_speed(time: f32) -> f32 {
+    time.sin() * 2.0
+}
+
+fn main() {
+    App::new()
+        .add_systems(Startup, setup)
+        .add_systems(Update, (rotate.run_if(should_rotate), spawn, count, frames))
+        .add_systems(Update, (spawn_enemy::<Goblin>, spawn_enemy::<enemies::Orc>))
+        .add_systems(Update, |time: Res<Time>| {
+            spin_speed(time.elapsed_secs());
+        });
+}